@@ -0,0 +1,196 @@
+//! Derives `pils::convert::IntoValue` and `pils::convert::FromValue`.
+//!
+//! Structs with named fields become maps keyed by field name, like `#{ x 1 y 2 }`,
+//! and tuple structs become q-expressions of their fields.
+//! Unit enum variants become symbols, and other variants q-expressions
+//! starting with the variant name, like `{Circle 5}` or `{Rect #{ w 1 h 2 }}`.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Fields, Generics, Ident};
+
+#[proc_macro_derive(IntoValue)]
+pub fn derive_into_value(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let generics = with_bound(input.generics.clone(), quote!(::pils::convert::IntoValue));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let (pattern, value) = into_fields(&data.fields);
+            quote! {
+                let Self #pattern = self;
+                #value
+            }
+        }
+        Data::Enum(data) => {
+            let arms = data.variants.iter().map(|variant| {
+                let ident = &variant.ident;
+                let tag = ident.to_string();
+                let (pattern, value) = into_fields(&variant.fields);
+                match &variant.fields {
+                    Fields::Unit => quote! {
+                        Self::#ident => ::pils::convert::derive::unit_variant(#tag),
+                    },
+                    _ => quote! {
+                        Self::#ident #pattern => ::pils::convert::derive::variant_with(#tag, #value),
+                    },
+                }
+            });
+            quote! {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+        Data::Union(_) => {
+            return syn::Error::new(Span::call_site(), "IntoValue cannot be derived for unions")
+                .to_compile_error()
+                .into()
+        }
+    };
+
+    quote! {
+        impl #impl_generics ::pils::convert::IntoValue for #name #ty_generics #where_clause {
+            fn into_value(self) -> ::pils::Value {
+                #body
+            }
+        }
+    }
+    .into()
+}
+
+#[proc_macro_derive(FromValue)]
+pub fn derive_from_value(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let generics = with_bound(input.generics.clone(), quote!(::pils::convert::FromValue));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let body = match &input.data {
+        Data::Struct(data) => from_fields(&quote!(Self), &name.to_string(), &data.fields),
+        Data::Enum(data) => {
+            let arms = data.variants.iter().map(|variant| {
+                let ident = &variant.ident;
+                let tag = ident.to_string();
+                let construct = from_fields(
+                    &quote!(Self::#ident),
+                    &format!("{name}::{ident}"),
+                    &variant.fields,
+                );
+                match &variant.fields {
+                    Fields::Named(_) => quote! {
+                        #tag => {
+                            let mut elements =
+                                ::pils::convert::derive::Elements::new(value, #tag, 1)?;
+                            let value = elements.take::<::pils::Value>()?;
+                            #construct
+                        }
+                    },
+                    _ => quote! {
+                        #tag => { #construct }
+                    },
+                }
+            });
+            let type_name = name.to_string();
+            quote! {
+                let (variant, value) = ::pils::convert::derive::variant(value, #type_name)?;
+                match variant.as_str() {
+                    #(#arms)*
+                    other => ::std::result::Result::Err(::pils::convert::derive::unknown_variant(
+                        #type_name, other,
+                    )),
+                }
+            }
+        }
+        Data::Union(_) => {
+            return syn::Error::new(Span::call_site(), "FromValue cannot be derived for unions")
+                .to_compile_error()
+                .into()
+        }
+    };
+
+    quote! {
+        impl #impl_generics ::pils::convert::FromValue for #name #ty_generics #where_clause {
+            fn from_value(value: ::pils::Value) -> ::std::result::Result<Self, ::pils::convert::derive::Error> {
+                #body
+            }
+        }
+    }
+    .into()
+}
+
+/// Requires every type parameter to implement `bound`.
+fn with_bound(mut generics: Generics, bound: TokenStream2) -> Generics {
+    for param in generics.type_params_mut() {
+        param.bounds.push(parse_quote!(#bound));
+    }
+    generics
+}
+
+/// A pattern destructuring the fields, and an expression converting them to a value.
+fn into_fields(fields: &Fields) -> (TokenStream2, TokenStream2) {
+    match fields {
+        Fields::Named(named) => {
+            let idents = named
+                .named
+                .iter()
+                .map(|f| f.ident.clone().unwrap())
+                .collect::<Vec<_>>();
+            let keys = idents.iter().map(Ident::to_string);
+            (
+                quote!({ #(#idents),* }),
+                quote! {
+                    ::pils::convert::derive::fields([
+                        #((#keys, ::pils::convert::IntoValue::into_value(#idents))),*
+                    ])
+                },
+            )
+        }
+        Fields::Unnamed(unnamed) => {
+            let idents = (0..unnamed.unnamed.len())
+                .map(|i| format_ident!("field{i}"))
+                .collect::<Vec<_>>();
+            (
+                quote!(( #(#idents),* )),
+                quote! {
+                    ::pils::convert::derive::elements([
+                        #(::pils::convert::IntoValue::into_value(#idents)),*
+                    ])
+                },
+            )
+        }
+        Fields::Unit => (
+            TokenStream2::new(),
+            quote!(::pils::convert::derive::elements([])),
+        ),
+    }
+}
+
+/// An expression building `constructor` from `value`, which holds the converted fields.
+fn from_fields(constructor: &TokenStream2, name: &str, fields: &Fields) -> TokenStream2 {
+    match fields {
+        Fields::Named(named) => {
+            let idents = named.named.iter().map(|f| f.ident.clone().unwrap());
+            let keys = idents.clone().map(|i| i.to_string());
+            quote! {
+                let mut fields = ::pils::convert::derive::Fields::new(value, #name)?;
+                ::std::result::Result::Ok(#constructor { #(#idents: fields.take(#keys)?),* })
+            }
+        }
+        Fields::Unnamed(unnamed) => {
+            let count = unnamed.unnamed.len();
+            let takes = (0..count).map(|_| quote!(elements.take()?));
+            quote! {
+                let mut elements = ::pils::convert::derive::Elements::new(value, #name, #count)?;
+                ::std::result::Result::Ok(#constructor( #(#takes),* ))
+            }
+        }
+        Fields::Unit => quote! {
+            ::pils::convert::derive::Elements::new(value, #name, 0)?;
+            ::std::result::Result::Ok(#constructor)
+        },
+    }
+}
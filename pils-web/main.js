@@ -1,4 +1,6 @@
-import init, { process_str, help_text, get_env_json, get_env_tuples } from "./pkg/pils_web.js";
+import init, { process_str, help_text, get_env_json, get_env_tuples, save_image, load_image } from "./pkg/pils_web.js";
+
+const IMAGE_KEY = "pils-image";
 
 let messages, btn, input;
 
@@ -13,6 +15,27 @@ function sendMessage() {
     addMessage("pils", result, "item-primary");
     let env = get_env_tuples();
     updateEnvironment(env);
+    saveImage();
+  }
+}
+
+function saveImage() {
+  try {
+    localStorage.setItem(IMAGE_KEY, save_image());
+  } catch (e) {
+    console.log(`Failed to save image: ${e}`);
+  }
+}
+
+function restoreImage() {
+  let image = localStorage.getItem(IMAGE_KEY);
+  if (image === null) return;
+  try {
+    load_image(image);
+    updateEnvironment(get_env_tuples());
+  } catch (e) {
+    console.log(`Failed to restore image: ${e}`);
+    localStorage.removeItem(IMAGE_KEY);
   }
 }
 
@@ -45,5 +68,6 @@ document.addEventListener("DOMContentLoaded", () => {
       if (e.key == "Enter") sendMessage();
     });
     addMessage("pils", help_text(), "item-primary");
+    restoreImage();
   });
 });
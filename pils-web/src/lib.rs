@@ -1,11 +1,52 @@
+use pils::config::Config;
+use std::sync::Once;
 use wasm_bindgen::prelude::*;
 
+/// How many evaluation steps a program typed into the playground may take,
+/// so that endless loops do not freeze the tab.
+const FUEL: u64 = 1_000_000;
+
+/// How many bytes the definitions of the playground may take.
+const MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+
+/// How deeply evaluations may nest, so that recursion like `eval loop` fails before
+/// overflowing the stack, which fuel alone does not prevent.
+const MAX_DEPTH: usize = 100;
+
+/// Applies the playground limits before the interpreter is first used, from whichever entry
+/// point comes first.
+fn configure() {
+    static CONFIGURE: Once = Once::new();
+    CONFIGURE.call_once(|| {
+        pils::configure(Config {
+            fuel: Some(FUEL),
+            memory_limit: Some(MEMORY_LIMIT),
+            max_depth: Some(MAX_DEPTH),
+            ..Config::default()
+        });
+    });
+}
+
 #[wasm_bindgen]
 #[must_use]
 pub fn process_str(line: &str) -> String {
+    configure();
     pils::process_str(line)
 }
 
+/// The definitions made so far, to be stored and restored with `load_image`.
+#[wasm_bindgen]
+pub fn save_image() -> Result<String, JsError> {
+    configure();
+    pils::save_image_str().map_err(|e| JsError::new(&format!("{e:#}")))
+}
+
+#[wasm_bindgen]
+pub fn load_image(image: &str) -> Result<(), JsError> {
+    configure();
+    pils::load_image_str(image).map_err(|e| JsError::new(&format!("{e:#}")))
+}
+
 #[wasm_bindgen]
 #[must_use]
 pub fn help_text() -> String {
@@ -15,12 +56,14 @@ pub fn help_text() -> String {
 #[wasm_bindgen]
 #[must_use]
 pub fn get_env_json() -> String {
+    configure();
     pils::get_env_json()
 }
 
 #[wasm_bindgen]
 #[must_use]
 pub fn get_env_tuples() -> String {
+    configure();
     pils::get_env_tuples()
 }
 
@@ -29,3 +72,22 @@ pub fn get_env_tuples() -> String {
 pub fn get_example_environment() -> String {
     pils::get_example_environment()
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn survives_runaway_programs() {
+        assert_eq!(process_str("def {loop} {eval loop}"), "()");
+        assert_eq!(
+            process_str("eval loop"),
+            "Error: Evaluation nested deeper than 100 levels"
+        );
+        assert_eq!(
+            process_str("+ 9223372036854775807 1"),
+            "Error: Integer overflow"
+        );
+        assert_eq!(process_str("+ 1 2"), "3");
+    }
+}
@@ -0,0 +1,38 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use pils::process;
+
+fn large_list(len: usize) -> String {
+    let items = (0..len)
+        .map(|n| n.to_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!("{{ {items} }}")
+}
+
+fn lists(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lists");
+    for len in [100, 10_000] {
+        let name = format!("big{len}");
+        process(&format!("def {{{name}}} {}", large_list(len))).unwrap();
+
+        group.bench_with_input(BenchmarkId::new("lookup", len), &name, |b, name| {
+            b.iter(|| process(name).unwrap());
+        });
+        group.bench_with_input(BenchmarkId::new("head", len), &name, |b, name| {
+            let line = format!("head {name}");
+            b.iter(|| process(&line).unwrap());
+        });
+        group.bench_with_input(BenchmarkId::new("tail", len), &name, |b, name| {
+            let line = format!("tail {name}");
+            b.iter(|| process(&line).unwrap());
+        });
+        group.bench_with_input(BenchmarkId::new("join", len), &name, |b, name| {
+            let line = format!("join (list {name} {name})");
+            b.iter(|| process(&line).unwrap());
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, lists);
+criterion_main!(benches);
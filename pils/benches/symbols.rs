@@ -0,0 +1,24 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use pils::process;
+
+fn symbols(c: &mut Criterion) {
+    let names = (0..64).map(|n| format!("variable{n}")).collect::<Vec<_>>();
+    let values = (0..64).map(|n| n.to_string()).collect::<Vec<_>>();
+    process(&format!("def {{{}}} {}", names.join(" "), values.join(" "))).unwrap();
+
+    // Store the programs so that the benchmarks measure evaluation rather than parsing.
+    process(&format!("def {{sum}} {{+ {}}}", names.join(" "))).unwrap();
+    c.bench_function("symbols/sum", |b| b.iter(|| process("eval sum").unwrap()));
+
+    process(&format!(
+        "def {{nested}} {{eval {{head (list {})}}}}",
+        names.join(" ")
+    ))
+    .unwrap();
+    c.bench_function("symbols/nested", |b| {
+        b.iter(|| process("eval nested").unwrap());
+    });
+}
+
+criterion_group!(benches, symbols);
+criterion_main!(benches);
@@ -0,0 +1,45 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use pils::{config::Config, configure, process};
+
+fn define_programs() {
+    process("def {x y} 3 4").unwrap();
+    // A deep arithmetic expression that references the same variables over and over.
+    // Each level only adds a constant, so that the result stays far from overflowing.
+    let mut expr = "(+ x y)".to_string();
+    for _ in 0..64 {
+        expr = format!("(+ x (- {expr} (* x y)) (* y (- x y)))");
+    }
+    process(&format!("def {{arith}} {{{expr}}}")).unwrap();
+    // A tree of programs evaluating each other, 2^10 calls deep in total.
+    process("def {f0} {+ x y}").unwrap();
+    for n in 1..=10 {
+        process(&format!(
+            "def {{f{n}}} {{+ (eval f{m}) (eval f{m})}}",
+            m = n - 1
+        ))
+        .unwrap();
+    }
+}
+
+fn vm(c: &mut Criterion) {
+    define_programs();
+    let mut expected = None;
+    for (name, compile) in [("walk", false), ("compiled", true)] {
+        configure(Config {
+            compile,
+            ..Config::default()
+        });
+        // Both ways of evaluating must agree, or the comparison means nothing.
+        let result = (process("eval arith").unwrap(), process("eval f10").unwrap());
+        assert_eq!(*expected.get_or_insert_with(|| result.clone()), result);
+        c.bench_function(&format!("{name}/arith"), |b| {
+            b.iter(|| process("eval arith").unwrap());
+        });
+        c.bench_function(&format!("{name}/recursive"), |b| {
+            b.iter(|| process("eval f10").unwrap());
+        });
+    }
+}
+
+criterion_group!(benches, vm);
+criterion_main!(benches);
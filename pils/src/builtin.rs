@@ -1,8 +1,19 @@
 use anyhow::{Context, Ok};
 
 use crate::{
-    environment::Environment, function::Function, qexpr::Qexpr, sexpr::Sexpr, value::Value,
+    environment::Environment,
+    map::{Key, Map},
+    module, pattern,
+    qexpr::Qexpr,
+    set::Set,
+    sexpr::Sexpr,
+    symbol::Symbol,
+    types,
+    value::Value,
 };
+use im::Vector;
+use itertools::Itertools;
+use std::collections::{HashMap, HashSet};
 
 pub fn list(val: Value, _env: &mut Environment) -> Result<Value, anyhow::Error> {
     match val {
@@ -67,31 +78,368 @@ pub fn eval(val: Value, env: &mut Environment) -> Result<Value, anyhow::Error> {
                 Qexpr(q).eval(env)
             }
         }
-        Value::Qexpr(Qexpr(q)) => Sexpr(q).eval(env),
+        Value::Qexpr(q) => q.eval(env),
         _ => Err(anyhow::anyhow!("Wrong type passed to 'eval'")),
     }
 }
 
 pub fn def(val: Value, env: &mut Environment) -> Result<Value, anyhow::Error> {
+    define(val, env, Definition::Def)
+}
+
+/// Like `def`, but may also overwrite constants such as the builtins.
+pub fn redef(val: Value, env: &mut Environment) -> Result<Value, anyhow::Error> {
+    define(val, env, Definition::Redef)
+}
+
+/// Whether a definition may overwrite constants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Definition {
+    Def,
+    Redef,
+}
+
+impl Definition {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Def => "def",
+            Self::Redef => "redef",
+        }
+    }
+}
+
+fn define(val: Value, env: &mut Environment, kind: Definition) -> Result<Value, anyhow::Error> {
+    let name = kind.name();
     let Value::Sexpr(Sexpr(mut s)) = val else {
-        return Err(anyhow::anyhow!("non-qexpr passed to def"));
+        return Err(anyhow::anyhow!("non-qexpr passed to {name}"));
     };
-    let syms = s.pop_front().context("'def' called without arguments")?;
+    let syms = s
+        .pop_front()
+        .with_context(|| format!("'{name}' called without arguments"))?;
     let Value::Qexpr(q) = syms else {
-        return Err(anyhow::anyhow!("Function 'def' passed incorrect type"));
-    };
-    let syms =
-        q.0.iter()
-            .cloned()
-            .map(|sym| match sym {
-                Value::Sym(s) => Ok(s),
-                _ => Err(anyhow::anyhow!("'def' cannot define non-symbol")),
-            })
-            .collect::<Result<Vec<_>, anyhow::Error>>()?;
-    for (sym, val) in syms.iter().zip(s.into_iter()) {
-        env.0.insert(sym.clone(), val);
-    }
-    Ok(Value::Fun(Function(crate::builtin::eval)))
+        return Err(anyhow::anyhow!("Function '{name}' passed incorrect type"));
+    };
+    if q.0.len() != s.len() {
+        return Err(anyhow::anyhow!(
+            "'{name}' got {} names but {} values",
+            q.0.len(),
+            s.len()
+        ));
+    }
+    let mut params = Vec::new();
+    for names in &q.0 {
+        check_names(names, name, &mut params)?;
+    }
+    let mut seen = HashSet::new();
+    for param in params {
+        let (sym, _) = types::parse_param(param)?;
+        if !seen.insert(sym) {
+            return Err(anyhow::anyhow!("'{name}' binds '{sym}' more than once"));
+        }
+    }
+    let mut bindings = HashMap::new();
+    for (names, val) in q.0.iter().zip(&s) {
+        if !pattern::bind(names, val, &mut bindings)? {
+            return Err(anyhow::anyhow!(
+                "'{name}' cannot destructure {val} into {names}"
+            ));
+        }
+    }
+    let bindings = bindings
+        .into_iter()
+        .map(|(sym, val)| Ok((types::parse_param(sym)?, val)))
+        .collect::<Result<Vec<_>, anyhow::Error>>()?;
+    for ((sym, ty), val) in &bindings {
+        if kind == Definition::Def && env.is_constant(*sym) {
+            return Err(anyhow::anyhow!(
+                "'{sym}' is constant, use 'redef' to redefine it"
+            ));
+        }
+        // Names keep the type they were declared with until annotated with another.
+        if let Some(ty) = ty.or_else(|| env.declared.get(sym).copied()) {
+            types::check_binding(*sym, ty, val)?;
+        }
+    }
+    env.reserve_definitions(bindings.iter().map(|((sym, _), val)| (*sym, val)))?;
+    for ((sym, ty), val) in bindings {
+        env.define(sym, val);
+        if let Some(ty) = ty {
+            env.declared.insert(sym, ty);
+        }
+    }
+    Ok(Value::Sexpr(Sexpr(Vector::new())))
+}
+
+/// Names passed to `def` are symbols, or q-expressions of names to destructure.
+/// The symbols they bind are added to `params`.
+fn check_names(names: &Value, name: &str, params: &mut Vec<Symbol>) -> Result<(), anyhow::Error> {
+    match names {
+        Value::Sym(sym) if matches!(sym.as_str(), "_" | "&") => Ok(()),
+        Value::Sym(sym) => {
+            params.push(*sym);
+            Ok(())
+        }
+        Value::Qexpr(Qexpr(q)) => q
+            .iter()
+            .try_for_each(|names| check_names(names, name, params)),
+        _ => Err(anyhow::anyhow!("'{name}' cannot define non-symbol")),
+    }
+}
+
+/// Marks defined names as constant, like `const {x y}`, so that `def` cannot overwrite them.
+pub fn r#const(val: Value, env: &mut Environment) -> Result<Value, anyhow::Error> {
+    let Value::Sexpr(Sexpr(mut s)) = val else {
+        return Err(anyhow::anyhow!("non-sexpr passed to 'const'"));
+    };
+    let Some(Value::Qexpr(Qexpr(q))) = s.pop_front() else {
+        return Err(anyhow::anyhow!("Function 'const' passed incorrect type"));
+    };
+    let syms = q
+        .iter()
+        .map(|sym| match sym {
+            Value::Sym(sym) if env.bindings.contains_key(sym) => Ok(*sym),
+            Value::Sym(sym) => Err(anyhow::anyhow!("'const' on undefined '{sym}'")),
+            _ => Err(anyhow::anyhow!("'const' on non-symbol")),
+        })
+        .collect::<Result<Vec<_>, anyhow::Error>>()?;
+    env.constants.extend(syms);
+    Ok(Value::Sexpr(Sexpr(Vector::new())))
+}
+
+/// Evaluates the body of the first arm whose pattern matches the value,
+/// like `match (list 1 2) {{x & _} {+ x 1} _ 0}`.
+/// Q-expression bodies are evaluated as code, with the variables of the pattern in scope.
+pub fn r#match(val: Value, env: &mut Environment) -> Result<Value, anyhow::Error> {
+    let Value::Sexpr(Sexpr(mut s)) = val else {
+        return Err(anyhow::anyhow!("non-sexpr passed to 'match'"));
+    };
+    let value = s.pop_front().context("'match' called without a value")?;
+    let Some(Value::Qexpr(Qexpr(arms))) = s.pop_front() else {
+        return Err(anyhow::anyhow!("'match' needs a q-expression of arms"));
+    };
+    if arms.len() % 2 != 0 {
+        return Err(anyhow::anyhow!("'match' arm without a body"));
+    }
+    for (pattern, body) in arms.iter().tuples() {
+        let Some(bindings) = pattern::matches(pattern, &value)? else {
+            continue;
+        };
+        return env.scoped(bindings, |env| match body.clone() {
+            Value::Qexpr(q) => q.eval(env),
+            v => v.eval(env),
+        });
+    }
+    Err(anyhow::anyhow!("No arm of 'match' matches {value}"))
+}
+
+/// Takes the symbols of a q-expression argument, like the `{a b}` of `export {a b}`.
+fn symbols_arg(val: Option<Value>, name: &str) -> Result<Vec<Symbol>, anyhow::Error> {
+    let Some(Value::Qexpr(Qexpr(q))) = val else {
+        return Err(anyhow::anyhow!("Function '{name}' passed incorrect type"));
+    };
+    q.into_iter()
+        .map(|v| match v {
+            Value::Sym(sym) => Ok(sym),
+            _ => Err(anyhow::anyhow!("'{name}' on non-symbol")),
+        })
+        .collect()
+}
+
+/// Names the module being evaluated, like `module {math}`.
+/// Without it, a module is named after its file.
+pub fn module(val: Value, env: &mut Environment) -> Result<Value, anyhow::Error> {
+    let Value::Sexpr(Sexpr(mut s)) = val else {
+        return Err(anyhow::anyhow!("non-sexpr passed to 'module'"));
+    };
+    let [name] = symbols_arg(s.pop_front(), "module")?[..] else {
+        return Err(anyhow::anyhow!("'module' takes exactly one name"));
+    };
+    env.module = Some(name);
+    Ok(Value::Sexpr(Sexpr(Vector::new())))
+}
+
+/// Makes definitions available to modules importing this one, like `export {square cube}`.
+pub fn export(val: Value, env: &mut Environment) -> Result<Value, anyhow::Error> {
+    let Value::Sexpr(Sexpr(mut s)) = val else {
+        return Err(anyhow::anyhow!("non-sexpr passed to 'export'"));
+    };
+    env.exports.extend(symbols_arg(s.pop_front(), "export")?);
+    Ok(Value::Sexpr(Sexpr(Vector::new())))
+}
+
+/// Loads a module, like `import {lib/math}`, and defines its exports as `math/square`.
+/// The prefix defaults to the module name and may be given like `import {lib/math} {m}`.
+pub fn import(val: Value, env: &mut Environment) -> Result<Value, anyhow::Error> {
+    let Value::Sexpr(Sexpr(mut s)) = val else {
+        return Err(anyhow::anyhow!("non-sexpr passed to 'import'"));
+    };
+    let [path] = symbols_arg(s.pop_front(), "import")?[..] else {
+        return Err(anyhow::anyhow!("'import' takes exactly one module"));
+    };
+    let prefix = match s.pop_front() {
+        None => None,
+        prefix => match symbols_arg(prefix, "import")?[..] {
+            [prefix] => Some(prefix),
+            _ => return Err(anyhow::anyhow!("'import' takes exactly one prefix")),
+        },
+    };
+    let file = module::resolve(path, env)?;
+    let module = module::load(&file, env)?;
+    let prefix = prefix.unwrap_or(module.name);
+    let exports = module
+        .exports
+        .iter()
+        .map(|(name, value)| (Symbol::new(&format!("{prefix}/{name}")), value))
+        .collect::<Vec<_>>();
+    env.reserve_definitions(exports.iter().copied())?;
+    for (sym, value) in exports {
+        env.define(sym, value.clone());
+    }
+    Ok(Value::Sexpr(Sexpr(Vector::new())))
+}
+
+/// Splits the arguments of a map builtin into the map and the remaining arguments.
+fn map_args(val: Value, name: &str) -> Result<(Map, Vector<Value>), anyhow::Error> {
+    let Value::Sexpr(Sexpr(mut args)) = val else {
+        return Err(anyhow::anyhow!("non-sexpr passed to '{name}'"));
+    };
+    let Some(Value::Map(map)) = args.pop_front() else {
+        return Err(anyhow::anyhow!("Function '{name}' passed non-map"));
+    };
+    Ok((map, args))
+}
+
+/// Keys are passed quoted like `{a b}`, since a bare symbol would be evaluated.
+/// A single number may also be passed as is.
+fn keys_arg(val: Option<Value>, name: &str) -> Result<Vec<Key>, anyhow::Error> {
+    match val.with_context(|| format!("'{name}' called without keys"))? {
+        Value::Qexpr(Qexpr(q)) => q.into_iter().map(Key::try_from).collect(),
+        v => Ok(vec![Key::try_from(v)?]),
+    }
+}
+
+fn key_arg(val: Option<Value>, name: &str) -> Result<Key, anyhow::Error> {
+    let mut keys = keys_arg(val, name)?;
+    if keys.len() != 1 {
+        return Err(anyhow::anyhow!("'{name}' takes exactly one key"));
+    }
+    Ok(keys.remove(0))
+}
+
+pub fn get(val: Value, _env: &mut Environment) -> Result<Value, anyhow::Error> {
+    let (map, mut args) = map_args(val, "get")?;
+    map.get(&key_arg(args.pop_front(), "get")?)
+}
+
+pub fn assoc(val: Value, _env: &mut Environment) -> Result<Value, anyhow::Error> {
+    let (map, mut args) = map_args(val, "assoc")?;
+    let keys = keys_arg(args.pop_front(), "assoc")?;
+    if keys.len() != args.len() {
+        return Err(anyhow::anyhow!(
+            "'assoc' got {} keys but {} values",
+            keys.len(),
+            args.len()
+        ));
+    }
+    Ok(map.assoc(keys.into_iter().zip(args)))
+}
+
+pub fn dissoc(val: Value, _env: &mut Environment) -> Result<Value, anyhow::Error> {
+    let (map, mut args) = map_args(val, "dissoc")?;
+    Ok(map.dissoc(&keys_arg(args.pop_front(), "dissoc")?))
+}
+
+pub fn keys(val: Value, _env: &mut Environment) -> Result<Value, anyhow::Error> {
+    let (map, _args) = map_args(val, "keys")?;
+    Ok(map.keys())
+}
+
+pub fn vals(val: Value, _env: &mut Environment) -> Result<Value, anyhow::Error> {
+    let (map, _args) = map_args(val, "vals")?;
+    Ok(map.vals())
+}
+
+pub fn has(val: Value, _env: &mut Environment) -> Result<Value, anyhow::Error> {
+    let (map, mut args) = map_args(val, "has?")?;
+    Ok(map.has(&key_arg(args.pop_front(), "has?")?))
+}
+
+pub fn set(val: Value, _env: &mut Environment) -> Result<Value, anyhow::Error> {
+    let Value::Sexpr(Sexpr(s)) = val else {
+        return Err(anyhow::anyhow!("non-sexpr passed to 'set'"));
+    };
+    Ok(Value::Set(s.into_iter().collect()))
+}
+
+/// Collects the arguments of a set builtin, all of which must be sets.
+fn set_args(val: Value, name: &str) -> Result<(Set, Vec<Set>), anyhow::Error> {
+    let Value::Sexpr(Sexpr(s)) = val else {
+        return Err(anyhow::anyhow!("non-sexpr passed to '{name}'"));
+    };
+    let mut sets = s
+        .into_iter()
+        .map(|v| match v {
+            Value::Set(s) => Ok(s),
+            _ => Err(anyhow::anyhow!("Function '{name}' passed non-set")),
+        })
+        .collect::<Result<Vec<_>, anyhow::Error>>()?;
+    if sets.is_empty() {
+        return Err(anyhow::anyhow!("'{name}' called without arguments"));
+    }
+    let first = sets.remove(0);
+    Ok((first, sets))
+}
+
+pub fn union(val: Value, _env: &mut Environment) -> Result<Value, anyhow::Error> {
+    let (first, rest) = set_args(val, "union")?;
+    Ok(first.union(rest))
+}
+
+pub fn intersection(val: Value, _env: &mut Environment) -> Result<Value, anyhow::Error> {
+    let (first, rest) = set_args(val, "intersection")?;
+    Ok(first.intersection(rest))
+}
+
+pub fn difference(val: Value, _env: &mut Environment) -> Result<Value, anyhow::Error> {
+    let (first, rest) = set_args(val, "difference")?;
+    Ok(first.difference(rest))
+}
+
+pub fn member(val: Value, _env: &mut Environment) -> Result<Value, anyhow::Error> {
+    let Value::Sexpr(Sexpr(mut s)) = val else {
+        return Err(anyhow::anyhow!("non-sexpr passed to 'member?'"));
+    };
+    let Some(Value::Set(set)) = s.pop_front() else {
+        return Err(anyhow::anyhow!("Function 'member?' passed non-set"));
+    };
+    let value = s.pop_front().context("'member?' called without a value")?;
+    Ok(set.member(&value))
+}
+
+fn str_arg(val: Value, name: &str) -> Result<String, anyhow::Error> {
+    let Value::Sexpr(Sexpr(mut s)) = val else {
+        return Err(anyhow::anyhow!("non-sexpr passed to '{name}'"));
+    };
+    match s.pop_front() {
+        Some(Value::Str(s)) => Ok(s),
+        _ => Err(anyhow::anyhow!("Function '{name}' passed non-string")),
+    }
+}
+
+pub fn json_parse(val: Value, _env: &mut Environment) -> Result<Value, anyhow::Error> {
+    let text = str_arg(val, "json-parse")?;
+    Value::from_json_str(&text).context("'json-parse' passed invalid JSON")
+}
+
+pub fn json_emit(val: Value, _env: &mut Environment) -> Result<Value, anyhow::Error> {
+    let Value::Sexpr(Sexpr(mut s)) = val else {
+        return Err(anyhow::anyhow!("non-sexpr passed to 'json-emit'"));
+    };
+    let json = s
+        .pop_front()
+        .context("'json-emit' called without a value")?
+        .to_json()?;
+    Ok(Value::Str(json.to_string()))
 }
 
 pub fn add(val: Value, _env: &mut Environment) -> Result<Value, anyhow::Error> {
@@ -0,0 +1,34 @@
+/// Settings that change how programs are evaluated, but not their results.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Compile programs to bytecode and run them on the virtual machine
+    /// instead of walking the expression tree.
+    pub compile: bool,
+    /// Simplify constant parts of programs, like `+ 1 2`, before evaluating them.
+    /// Programs that may bind names, like `def`, are left as they are.
+    pub fold_constants: bool,
+    /// Check types before evaluating programs and refuse to run those with mismatches.
+    pub check_types: bool,
+    /// How many evaluation steps a program may take before it is stopped, if limited.
+    /// Untrusted programs may loop forever otherwise.
+    pub fuel: Option<u64>,
+    /// How deeply evaluations may nest, like `eval` evaluating code that calls `eval`, if limited.
+    /// Each level takes stack space, so untrusted programs may overflow the stack otherwise.
+    pub max_depth: Option<usize>,
+    /// How many bytes definitions and the values computed from them may take, if limited.
+    /// Sizes are estimated, see [`crate::Value::size`].
+    pub memory_limit: Option<usize>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            compile: false,
+            fold_constants: true,
+            check_types: false,
+            fuel: None,
+            max_depth: None,
+            memory_limit: None,
+        }
+    }
+}
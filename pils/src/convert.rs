@@ -0,0 +1,413 @@
+//! Conversions between Rust types and pils values.
+//!
+//! Numbers convert to `Num`, strings to `Str` (and back from strings or symbols), and `Vec`s and tuples to q-expressions.
+//! `Option`s become q-expressions holding zero or one element, so that `Some({})` and `None` differ.
+//! Structs and enums can derive the conversions, see [`pils_derive`].
+
+use crate::{
+    map::{Key, Map},
+    qexpr::Qexpr,
+    sexpr::Sexpr,
+    symbol::Symbol,
+    value::Value,
+};
+use anyhow::Context;
+pub use pils_derive::{FromValue, IntoValue};
+
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+pub trait FromValue: Sized {
+    fn from_value(value: Value) -> Result<Self, anyhow::Error>;
+}
+
+/// The arguments of a call from Rust: a tuple of values, each converted on its own,
+/// or a `Vec` of `Value`s.
+pub trait IntoArgs {
+    fn into_args(self) -> Vec<Value>;
+}
+
+impl IntoArgs for () {
+    fn into_args(self) -> Vec<Value> {
+        Vec::new()
+    }
+}
+
+impl IntoArgs for Vec<Value> {
+    fn into_args(self) -> Vec<Value> {
+        self
+    }
+}
+
+impl<const N: usize> IntoArgs for [Value; N] {
+    fn into_args(self) -> Vec<Value> {
+        self.into()
+    }
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+impl FromValue for Value {
+    fn from_value(value: Value) -> Result<Self, anyhow::Error> {
+        Ok(value)
+    }
+}
+
+macro_rules! integers {
+    ($($t:ty),*) => {
+        $(
+            impl FromValue for $t {
+                fn from_value(value: Value) -> Result<Self, anyhow::Error> {
+                    let Value::Num(n) = value else {
+                        return Err(anyhow::anyhow!("expected a number, found '{value}'"));
+                    };
+                    <$t>::try_from(n)
+                        .with_context(|| format!("{n} does not fit into {}", stringify!($t)))
+                }
+            }
+        )*
+    };
+}
+
+integers!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+/// Only integers that always fit into a number are values.
+/// Convert others, like `usize`, with `i64::try_from` first.
+macro_rules! lossless_integers {
+    ($($t:ty),*) => {
+        $(
+            impl IntoValue for $t {
+                fn into_value(self) -> Value {
+                    Value::Num(i64::from(self))
+                }
+            }
+        )*
+    };
+}
+
+lossless_integers!(i8, i16, i32, i64, u8, u16, u32);
+
+/// Booleans are numbers, like the results of `has?` and `member?`: 1 is true and 0 is false.
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        Value::Num(i64::from(self))
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: Value) -> Result<Self, anyhow::Error> {
+        match value {
+            Value::Num(0) => Ok(false),
+            Value::Num(1) => Ok(true),
+            v => Err(anyhow::anyhow!("expected 0 or 1, found '{v}'")),
+        }
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Value {
+        Value::Str(self)
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Value {
+        Value::Str(self.to_string())
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: Value) -> Result<Self, anyhow::Error> {
+        match value {
+            Value::Sym(sym) => Ok(sym.as_str().to_string()),
+            Value::Str(s) => Ok(s),
+            v => Err(anyhow::anyhow!("expected a symbol or string, found '{v}'")),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value {
+        Value::Qexpr(self.into_iter().map(IntoValue::into_value).collect())
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: Value) -> Result<Self, anyhow::Error> {
+        elements(value)?.into_iter().map(T::from_value).collect()
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Value {
+        Value::Qexpr(self.into_iter().map(IntoValue::into_value).collect())
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: Value) -> Result<Self, anyhow::Error> {
+        let mut elements = elements(value)?;
+        match elements.len() {
+            0 => Ok(None),
+            1 => T::from_value(elements.remove(0)).map(Some),
+            n => Err(anyhow::anyhow!("expected at most one element, found {n}")),
+        }
+    }
+}
+
+macro_rules! tuples {
+    ($($len:literal => ($($t:ident),+)),*) => {
+        $(
+            impl<$($t: IntoValue),+> IntoValue for ($($t,)+) {
+                #[allow(non_snake_case)]
+                fn into_value(self) -> Value {
+                    let ($($t,)+) = self;
+                    Value::Qexpr(Qexpr::from_iter([$($t.into_value()),+]))
+                }
+            }
+
+            impl<$($t: IntoValue),+> IntoArgs for ($($t,)+) {
+                #[allow(non_snake_case)]
+                fn into_args(self) -> Vec<Value> {
+                    let ($($t,)+) = self;
+                    vec![$($t.into_value()),+]
+                }
+            }
+
+            impl<$($t: FromValue),+> FromValue for ($($t,)+) {
+                fn from_value(value: Value) -> Result<Self, anyhow::Error> {
+                    let mut elements = derive::Elements::new(value, "tuple", $len)?;
+                    Ok(($(elements.take::<$t>()?,)+))
+                }
+            }
+        )*
+    };
+}
+
+tuples!(
+    1 => (A),
+    2 => (A, B),
+    3 => (A, B, C),
+    4 => (A, B, C, D),
+    5 => (A, B, C, D, E),
+    6 => (A, B, C, D, E, F)
+);
+
+/// The elements of a q-expression, or of an s-expression like the arguments of a builtin.
+fn elements(value: Value) -> Result<Vec<Value>, anyhow::Error> {
+    match value {
+        Value::Qexpr(Qexpr(q)) | Value::Sexpr(Sexpr(q)) => Ok(q.into_iter().collect()),
+        v => Err(anyhow::anyhow!("expected a q-expression, found '{v}'")),
+    }
+}
+
+/// Helpers for the code generated by the derive macros.
+#[doc(hidden)]
+pub mod derive {
+    use super::{elements as elements_of, FromValue, Key, Map, Qexpr, Symbol, Value};
+    use anyhow::Context;
+    pub use anyhow::Error;
+    use im::OrdMap;
+
+    pub fn fields<const N: usize>(fields: [(&str, Value); N]) -> Value {
+        Value::Map(Map(fields
+            .into_iter()
+            .map(|(name, value)| (Key::Sym(Symbol::new(name)), value))
+            .collect()))
+    }
+
+    pub fn elements<const N: usize>(elements: [Value; N]) -> Value {
+        Value::Qexpr(Qexpr::from_iter(elements))
+    }
+
+    pub fn unit_variant(name: &str) -> Value {
+        Value::Sym(Symbol::new(name))
+    }
+
+    /// A variant with fields is a q-expression starting with the variant name. Its fields
+    /// follow either one by one, or as a map if they are named.
+    pub fn variant_with(name: &str, fields: Value) -> Value {
+        let tag = Value::Sym(Symbol::new(name));
+        match fields {
+            Value::Qexpr(Qexpr(mut q)) => {
+                q.push_front(tag);
+                Value::Qexpr(Qexpr(q))
+            }
+            fields => Value::Qexpr(Qexpr::from_iter([tag, fields])),
+        }
+    }
+
+    /// Splits a variant into its name and a q-expression of what follows it.
+    pub fn variant(value: Value, type_name: &str) -> Result<(String, Value), anyhow::Error> {
+        match value {
+            Value::Sym(sym) => Ok((sym.as_str().to_string(), Value::Qexpr(Qexpr::from_iter([])))),
+            Value::Qexpr(Qexpr(mut q)) => match q.pop_front() {
+                Some(Value::Sym(sym)) => Ok((sym.as_str().to_string(), Value::Qexpr(Qexpr(q)))),
+                _ => Err(anyhow::anyhow!(
+                    "{type_name} variant does not start with its name"
+                )),
+            },
+            v => Err(anyhow::anyhow!(
+                "expected a {type_name} variant, found '{v}'"
+            )),
+        }
+    }
+
+    pub fn unknown_variant(type_name: &str, name: &str) -> anyhow::Error {
+        anyhow::anyhow!("{type_name} has no variant '{name}'")
+    }
+
+    /// The named fields of a struct, as a map.
+    pub struct Fields {
+        fields: OrdMap<Key, Value>,
+        name: &'static str,
+    }
+
+    impl Fields {
+        pub fn new(value: Value, name: &'static str) -> Result<Self, anyhow::Error> {
+            match value {
+                Value::Map(Map(fields)) => Ok(Self { fields, name }),
+                v => Err(anyhow::anyhow!("expected a map for {name}, found '{v}'")),
+            }
+        }
+
+        pub fn take<T: FromValue>(&mut self, field: &str) -> Result<T, anyhow::Error> {
+            let value = self
+                .fields
+                .remove(&Key::Sym(Symbol::new(field)))
+                .with_context(|| format!("{} is missing field '{field}'", self.name))?;
+            T::from_value(value).with_context(|| format!("in field '{field}' of {}", self.name))
+        }
+    }
+
+    /// The unnamed fields of a tuple or tuple struct, as a q-expression.
+    pub struct Elements {
+        elements: std::vec::IntoIter<Value>,
+        name: &'static str,
+        index: usize,
+    }
+
+    impl Elements {
+        pub fn new(value: Value, name: &'static str, len: usize) -> Result<Self, anyhow::Error> {
+            let elements = elements_of(value).with_context(|| format!("in {name}"))?;
+            if elements.len() != len {
+                return Err(anyhow::anyhow!(
+                    "{name} has {len} elements, found {}",
+                    elements.len()
+                ));
+            }
+            Ok(Self {
+                elements: elements.into_iter(),
+                name,
+                index: 0,
+            })
+        }
+
+        pub fn take<T: FromValue>(&mut self) -> Result<T, anyhow::Error> {
+            let value = self.elements.next().context("no elements left")?;
+            self.index += 1;
+            T::from_value(value)
+                .with_context(|| format!("in element {} of {}", self.index, self.name))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse;
+
+    fn value(input: &str) -> Value {
+        let Value::Sexpr(Sexpr(s)) = parse(input).unwrap() else {
+            unreachable!()
+        };
+        s[0].clone()
+    }
+
+    fn round_trip<T: IntoValue + FromValue + PartialEq + std::fmt::Debug + Clone>(
+        rust: T,
+        pils: &str,
+    ) {
+        assert_eq!(rust.clone().into_value().to_string(), pils);
+        assert_eq!(T::from_value(value(pils)).unwrap(), rust);
+    }
+
+    #[derive(Debug, Clone, PartialEq, IntoValue, FromValue)]
+    struct Point {
+        x: i64,
+        y: i64,
+    }
+
+    #[derive(Debug, Clone, PartialEq, IntoValue, FromValue)]
+    struct Named<T>(String, T);
+
+    #[derive(Debug, Clone, PartialEq, IntoValue, FromValue)]
+    enum Shape {
+        Empty,
+        Circle(u32),
+        Rect { corner: Point, size: (u8, u8) },
+    }
+
+    #[test]
+    fn converts_builtin_types() {
+        round_trip(42_i32, "42");
+        round_trip(true, "1");
+        round_trip("abc".to_string(), r#""abc""#);
+        round_trip("two words".to_string(), r#""two words""#);
+        assert_eq!(String::from_value(value("abc")).unwrap(), "abc");
+        round_trip(vec![1_u8, 2, 3], "{ 1 2 3 }");
+        round_trip((1_i64, vec![Some(2_i64), None]), "{ 1 { { 2 } {} } }");
+    }
+
+    #[test]
+    fn reports_mismatches() {
+        assert_eq!(
+            u8::from_value(Value::Num(300)).unwrap_err().to_string(),
+            "300 does not fit into u8"
+        );
+        assert_eq!(
+            usize::from_value(Value::Num(-1)).unwrap_err().to_string(),
+            "-1 does not fit into usize"
+        );
+        assert_eq!(
+            format!(
+                "{:#}",
+                <(i64, i64)>::from_value(value("{1 a}")).unwrap_err()
+            ),
+            "in element 2 of tuple: expected a number, found 'a'"
+        );
+        assert!(Vec::<i64>::from_value(Value::Num(1)).is_err());
+    }
+
+    #[test]
+    fn derives_structs() {
+        round_trip(Point { x: 1, y: -2 }, "#{ x 1 y -2 }");
+        round_trip(Named("p".to_string(), 5_i64), r#"{ "p" 5 }"#);
+        assert_eq!(
+            format!("{:#}", Point::from_value(value("#{ x 1 }")).unwrap_err()),
+            "Point is missing field 'y'"
+        );
+    }
+
+    #[test]
+    fn derives_enums() {
+        round_trip(Shape::Empty, "Empty");
+        round_trip(Shape::Circle(3), "{ Circle 3 }");
+        round_trip(
+            Shape::Rect {
+                corner: Point { x: 0, y: 1 },
+                size: (2, 3),
+            },
+            "{ Rect #{ corner #{ x 0 y 1 } size { 2 3 } } }",
+        );
+        assert_eq!(
+            Shape::from_value(value("Square")).unwrap_err().to_string(),
+            "Shape has no variant 'Square'"
+        );
+    }
+}
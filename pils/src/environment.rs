@@ -1,30 +1,365 @@
-use crate::{builtin, function::Function, value::Value};
+use crate::{
+    builtin,
+    config::Config,
+    function::{Arity, Function},
+    interrupt::{CancellationToken, Interrupt},
+    module::Modules,
+    symbol::{self, Symbol},
+    types::Type,
+    value::Value,
+    vm::Chunk,
+};
 use serde_derive::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// How many steps to take between checks for timeouts,
+/// which keeps reading the clock off the path of every step.
+const DEADLINE_INTERVAL: u64 = 16;
+
+/// How many compiled programs to keep around before evicting the least recently used.
+pub(crate) const CHUNK_CACHE_SIZE: usize = 1024;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Environment(pub(crate) HashMap<String, Value>);
+pub struct Environment {
+    pub(crate) bindings: HashMap<Symbol, Value>,
+    /// Types declared by annotated definitions like `def {x:Num} 1`.
+    #[serde(default)]
+    pub(crate) declared: HashMap<Symbol, Type>,
+    /// Names that `def` may not overwrite, like those of the builtins.
+    #[serde(default)]
+    pub(crate) constants: HashSet<Symbol>,
+    /// Local bindings, like those of a `match` arm, innermost last. They shadow global bindings.
+    #[serde(skip)]
+    scopes: Vec<HashMap<Symbol, Value>>,
+    /// Bumped whenever a binding changes, so that cached lookups can be invalidated.
+    #[serde(skip)]
+    pub(crate) generation: u64,
+    #[serde(skip)]
+    pub(crate) config: Config,
+    /// Evaluation steps taken by the current program, counted against the fuel.
+    #[serde(skip)]
+    pub(crate) steps: u64,
+    /// How deeply the evaluations of the current program are nested.
+    #[serde(skip)]
+    depth: usize,
+    /// The estimated size of the bindings, in bytes.
+    #[serde(skip)]
+    pub(crate) memory: usize,
+    /// The symbol bytes this thread had interned when the current evaluation started,
+    /// so that symbols it interns can be charged to `memory`.
+    #[serde(skip, default = "symbol::interned_bytes")]
+    pub(crate) interned: usize,
+    #[serde(skip)]
+    pub(crate) cancellation: CancellationToken,
+    /// When the current evaluation times out, if it has a timeout.
+    #[serde(skip)]
+    pub(crate) deadline: Option<(Instant, Duration)>,
+    /// The file being evaluated, which imports are resolved relative to.
+    #[serde(skip)]
+    pub(crate) file: Option<PathBuf>,
+    /// The name declared by `module`, if this environment evaluates a module.
+    #[serde(skip)]
+    pub(crate) module: Option<Symbol>,
+    /// The names declared by `export`.
+    #[serde(skip)]
+    pub(crate) exports: Vec<Symbol>,
+    #[serde(skip)]
+    pub(crate) modules: Modules,
+    /// Programs compiled so far, so that evaluating the same code again skips compilation,
+    /// along with when each was last used.
+    #[serde(skip)]
+    chunks: HashMap<Value, (Arc<Chunk>, u64)>,
+    /// Counts compilations, to order the uses of cached programs.
+    #[serde(skip)]
+    compilations: u64,
+}
+
+impl Environment {
+    pub(crate) fn get(&self, sym: Symbol) -> Option<&Value> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&sym))
+            .or_else(|| self.bindings.get(&sym))
+    }
+
+    pub(crate) fn is_constant(&self, sym: Symbol) -> bool {
+        self.constants.contains(&sym)
+    }
+
+    /// Evaluates `f` with `bindings` in scope.
+    pub(crate) fn scoped<T>(
+        &mut self,
+        bindings: HashMap<Symbol, Value>,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        self.scopes.push(bindings);
+        self.generation += 1;
+        let result = f(self);
+        self.scopes.pop();
+        self.generation += 1;
+        result
+    }
+
+    /// Whether `sym` is bound to the builtin of that name, rather than to a definition.
+    pub(crate) fn is_builtin(&self, sym: Symbol, value: &Value) -> bool {
+        matches!(value, Value::Fun(fun) if fun.name() == sym.as_str()) && self.is_constant(sym)
+    }
+
+    /// The bindings other than builtins, made by definitions.
+    pub(crate) fn definitions(&self) -> impl Iterator<Item = (&Symbol, &Value)> {
+        self.bindings
+            .iter()
+            .filter(|(sym, value)| !self.is_builtin(**sym, value))
+    }
+
+    /// Binds `sym` globally. The size of the binding is only tracked if memory is limited,
+    /// since computing it walks the whole value.
+    pub(crate) fn define(&mut self, sym: Symbol, value: Value) {
+        if let Some(limit) = self.config.memory_limit {
+            self.memory += value.size_within(limit).unwrap_or(limit);
+            if let Some(old) = self.bindings.get(&sym) {
+                let replaced = old.size_within(limit).unwrap_or(limit);
+                self.memory = self.memory.saturating_sub(replaced);
+            }
+        }
+        self.bindings.insert(sym, value);
+        self.generation += 1;
+    }
+
+    /// Changes the configuration. Setting a memory limit counts the memory taken by the bindings,
+    /// which is not tracked without one.
+    pub(crate) fn configure(&mut self, config: Config) {
+        self.config = config;
+        self.memory = 0;
+        if let Some(limit) = self.config.memory_limit {
+            for value in self.bindings.values() {
+                self.memory = self
+                    .memory
+                    .saturating_add(value.size_within(limit).unwrap_or(limit));
+            }
+        }
+        self.interned = symbol::interned_bytes();
+    }
+
+    /// Starts evaluating a program with all of its fuel, failing if it is already interrupted.
+    pub(crate) fn start(&mut self) -> Result<(), Interrupt> {
+        self.steps = 0;
+        self.depth = 0;
+        self.check_interrupted()
+    }
+
+    /// Runs `f` one level deeper, failing instead once evaluations nest beyond the maximum depth.
+    pub(crate) fn nested<R>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<R, anyhow::Error>,
+    ) -> Result<R, anyhow::Error> {
+        if let Some(max_depth) = self.config.max_depth {
+            if self.depth >= max_depth {
+                return Err(Interrupt::TooDeep { depth: max_depth }.into());
+            }
+        }
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Counts an evaluation step, failing once the program has used up its fuel,
+    /// was cancelled or ran out of time. The time is only checked every few steps.
+    pub(crate) fn step(&mut self) -> Result<(), Interrupt> {
+        self.steps += 1;
+        if let Some(fuel) = self.config.fuel {
+            if self.steps > fuel {
+                return Err(Interrupt::OutOfFuel { steps: fuel });
+            }
+        }
+        if self.cancellation.is_cancelled() {
+            return Err(Interrupt::Cancelled);
+        }
+        if self.steps.is_multiple_of(DEADLINE_INTERVAL) {
+            self.check_interrupted()?;
+        }
+        Ok(())
+    }
+
+    /// Fails if the evaluation was cancelled or ran out of time.
+    pub(crate) fn check_interrupted(&self) -> Result<(), Interrupt> {
+        if self.cancellation.is_cancelled() {
+            return Err(Interrupt::Cancelled);
+        }
+        match self.deadline {
+            Some((deadline, timeout)) if Instant::now() >= deadline => {
+                Err(Interrupt::TimedOut { timeout })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails if taking `value` in addition to the bindings and interned symbols would exceed
+    /// the memory limit. The size is only computed if memory is limited, and only up to the limit.
+    pub(crate) fn reserve(&self, value: &Value) -> Result<(), Interrupt> {
+        self.reserve_all([(None, value)])
+    }
+
+    /// Like [`Self::reserve`], for definitions that replace the bindings of the same names.
+    pub(crate) fn reserve_definitions<'a>(
+        &self,
+        definitions: impl IntoIterator<Item = (Symbol, &'a Value)>,
+    ) -> Result<(), Interrupt> {
+        self.reserve_all(
+            definitions
+                .into_iter()
+                .map(|(sym, value)| (Some(sym), value)),
+        )
+    }
+
+    fn reserve_all<'a>(
+        &self,
+        values: impl IntoIterator<Item = (Option<Symbol>, &'a Value)>,
+    ) -> Result<(), Interrupt> {
+        let Some(limit) = self.config.memory_limit else {
+            return Ok(());
+        };
+        let out_of_memory = Interrupt::OutOfMemory { limit };
+        let mut remaining = limit
+            .checked_sub(self.memory + self.newly_interned())
+            .ok_or(out_of_memory.clone())?;
+        for (sym, value) in values {
+            let replaced = sym
+                .and_then(|sym| self.bindings.get(&sym))
+                .map_or(0, |old| old.size_within(limit).unwrap_or(limit));
+            let available = remaining.saturating_add(replaced);
+            let size = value.size_within(available).ok_or(out_of_memory.clone())?;
+            remaining = available - size;
+        }
+        Ok(())
+    }
+
+    fn newly_interned(&self) -> usize {
+        symbol::interned_bytes().saturating_sub(self.interned)
+    }
+
+    /// Runs `f`, charging the symbols it interns to the memory of this environment,
+    /// since they are never freed.
+    pub(crate) fn metered<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        self.interned = symbol::interned_bytes();
+        let result = f(self);
+        if self.config.memory_limit.is_some() {
+            self.memory += self.newly_interned();
+        }
+        self.interned = symbol::interned_bytes();
+        result
+    }
+
+    pub(crate) fn compile(&mut self, program: Value) -> Arc<Chunk> {
+        self.compilations += 1;
+        if let Some((chunk, used)) = self.chunks.get_mut(&program) {
+            *used = self.compilations;
+            return chunk.clone();
+        }
+        if self.chunks.len() >= CHUNK_CACHE_SIZE {
+            let oldest = self
+                .chunks
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(program, _)| program.clone());
+            if let Some(oldest) = oldest {
+                self.chunks.remove(&oldest);
+            }
+        }
+        let chunk = Arc::new(Chunk::compile(program.clone()));
+        self.chunks
+            .insert(program, (chunk.clone(), self.compilations));
+        chunk
+    }
+
+    /// Defines a builtin, which is constant like those of pils.
+    pub(crate) fn add_function(&mut self, function: Function) {
+        let name = Symbol::new(function.name);
+        self.define(name, Value::Fun(function));
+        self.constants.insert(name);
+    }
+
+    fn add_builtin(
+        &mut self,
+        name: &'static str,
+        arity: Arity,
+        fun: fn(Value, &mut Self) -> Result<Value, anyhow::Error>,
+    ) {
+        self.add_function(Function::new(name, fun).with_unchecked_arity(arity));
+    }
+}
 
 impl Default for Environment {
     fn default() -> Self {
-        let mut m = Self(HashMap::new());
-        m.0.insert("list".to_string(), Value::Fun(Function(builtin::list)));
-        m.0.insert("head".to_string(), Value::Fun(Function(builtin::head)));
-        m.0.insert("tail".to_string(), Value::Fun(Function(builtin::tail)));
-        m.0.insert("join".to_string(), Value::Fun(Function(builtin::join)));
-        m.0.insert("eval".to_string(), Value::Fun(Function(builtin::eval)));
-        m.0.insert("def".to_string(), Value::Fun(Function(builtin::def)));
-
-        m.0.insert("+".to_string(), Value::Fun(Function(builtin::add)));
-        m.0.insert("-".to_string(), Value::Fun(Function(builtin::sub)));
-        m.0.insert("*".to_string(), Value::Fun(Function(builtin::mul)));
-        m.0.insert("/".to_string(), Value::Fun(Function(builtin::div)));
+        let mut m = Self::from_iter([]);
+        m.add_builtin("list", Arity::AtLeast(0), builtin::list);
+        m.add_builtin("head", Arity::Exactly(1), builtin::head);
+        m.add_builtin("tail", Arity::Exactly(1), builtin::tail);
+        m.add_builtin("join", Arity::Exactly(1), builtin::join);
+        m.add_builtin("eval", Arity::Exactly(1), builtin::eval);
+        m.add_builtin("def", Arity::AtLeast(1), builtin::def);
+        m.add_builtin("redef", Arity::AtLeast(1), builtin::redef);
+        m.add_builtin("const", Arity::Exactly(1), builtin::r#const);
+
+        m.add_builtin("module", Arity::Exactly(1), builtin::module);
+        m.add_builtin("export", Arity::Exactly(1), builtin::export);
+        m.add_builtin("import", Arity::AtLeast(1), builtin::import);
+        m.add_builtin("match", Arity::Exactly(2), builtin::r#match);
+
+        m.add_builtin("get", Arity::Exactly(2), builtin::get);
+        m.add_builtin("assoc", Arity::AtLeast(2), builtin::assoc);
+        m.add_builtin("dissoc", Arity::Exactly(2), builtin::dissoc);
+        m.add_builtin("keys", Arity::Exactly(1), builtin::keys);
+        m.add_builtin("vals", Arity::Exactly(1), builtin::vals);
+        m.add_builtin("has?", Arity::Exactly(2), builtin::has);
+
+        m.add_builtin("set", Arity::AtLeast(0), builtin::set);
+        m.add_builtin("union", Arity::AtLeast(1), builtin::union);
+        m.add_builtin("intersection", Arity::AtLeast(1), builtin::intersection);
+        m.add_builtin("difference", Arity::AtLeast(1), builtin::difference);
+        m.add_builtin("member?", Arity::Exactly(2), builtin::member);
+
+        m.add_builtin("json-parse", Arity::Exactly(1), builtin::json_parse);
+        m.add_builtin("json-emit", Arity::Exactly(1), builtin::json_emit);
+
+        m.add_builtin("+", Arity::AtLeast(1), builtin::add);
+        m.add_builtin("-", Arity::AtLeast(1), builtin::sub);
+        m.add_builtin("*", Arity::AtLeast(1), builtin::mul);
+        m.add_builtin("/", Arity::AtLeast(1), builtin::div);
+        // The names of the builtins are shared by all environments, so they are not charged.
+        m.interned = symbol::interned_bytes();
         m
     }
 }
 
-impl FromIterator<(String, Value)> for Environment {
-    fn from_iter<T: IntoIterator<Item = (String, Value)>>(iter: T) -> Self {
-        Self(iter.into_iter().collect())
+impl FromIterator<(Symbol, Value)> for Environment {
+    fn from_iter<T: IntoIterator<Item = (Symbol, Value)>>(iter: T) -> Self {
+        Self {
+            bindings: iter.into_iter().collect(),
+            declared: HashMap::new(),
+            constants: HashSet::new(),
+            scopes: Vec::new(),
+            file: None,
+            module: None,
+            exports: Vec::new(),
+            modules: Modules::default(),
+            generation: 0,
+            config: Config::default(),
+            steps: 0,
+            depth: 0,
+            memory: 0,
+            interned: symbol::interned_bytes(),
+            cancellation: CancellationToken::default(),
+            deadline: None,
+            chunks: HashMap::new(),
+            compilations: 0,
+        }
     }
 }
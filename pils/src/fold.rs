@@ -0,0 +1,178 @@
+use crate::{
+    environment::Environment,
+    qexpr::Qexpr,
+    sexpr::{Arithmetic, Sexpr},
+    value::Value,
+};
+
+/// Simplifies the parts of a program that do not depend on anything but literals:
+/// arithmetic on numbers, and `eval`, `head` and `tail` of literal q-expressions.
+///
+/// A call is only simplified if its operator is bound to the builtin of the same name,
+/// so redefining `+` disables folding of `+`. Calls that would fail, like division by zero,
+/// are left alone so that they fail when evaluated.
+///
+/// Programs that may change bindings while they run are not simplified at all, since the
+/// bindings seen here could then differ from the ones their calls are evaluated with.
+pub(crate) fn fold(value: Value, env: &Environment) -> Value {
+    if rebinds(&value, env) {
+        return value;
+    }
+    fold_constants(value, env)
+}
+
+fn fold_constants(value: Value, env: &Environment) -> Value {
+    match value {
+        Value::Sexpr(Sexpr(items)) => {
+            let items = items.into_iter().map(|v| fold_constants(v, env)).collect();
+            simplify(Sexpr(items), env)
+        }
+        // Q-expressions are data until evaluated.
+        v => v,
+    }
+}
+
+/// Whether evaluating `value` may bind names: it mentions, even quoted, a builtin that binds
+/// names, or a q-expression bound to a name, which could be evaluated as code.
+fn rebinds(value: &Value, env: &Environment) -> bool {
+    match value {
+        Value::Sym(sym) => {
+            matches!(sym.as_str(), "def" | "redef" | "import")
+                || match env.get(*sym) {
+                    Some(Value::Fun(fun)) => matches!(fun.name, "def" | "redef" | "import"),
+                    Some(Value::Qexpr(_)) => true,
+                    _ => false,
+                }
+        }
+        Value::Sexpr(Sexpr(items)) | Value::Qexpr(Qexpr(items)) => {
+            items.iter().any(|v| rebinds(v, env))
+        }
+        Value::Map(_) | Value::Set(_) | Value::Num(_) | Value::Str(_) | Value::Fun(_) => false,
+    }
+}
+
+fn simplify(sexpr: Sexpr, env: &Environment) -> Value {
+    // An s-expression with one element evaluates to what that element evaluates to.
+    if sexpr.0.len() == 1 {
+        return sexpr.0[0].clone();
+    }
+    let Some(Value::Sym(operator)) = sexpr.0.front() else {
+        return Value::Sexpr(sexpr);
+    };
+    let Some(Value::Fun(fun)) = env.get(*operator) else {
+        return Value::Sexpr(sexpr);
+    };
+    if fun.name != operator.as_str() {
+        return Value::Sexpr(sexpr);
+    }
+    let args = sexpr.0.clone().slice(1..);
+
+    if let Some(arithmetic) = Arithmetic::of(fun.name) {
+        let numbers = args
+            .iter()
+            .map(|v| match v {
+                Value::Num(n) => Some(*n),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>();
+        return match numbers.map(|n| arithmetic.apply(&n)) {
+            Some(Ok(n)) if !args.is_empty() => Value::Num(n),
+            _ => Value::Sexpr(sexpr),
+        };
+    }
+
+    let [Value::Qexpr(Qexpr(q))] = args.iter().collect::<Vec<_>>()[..] else {
+        return Value::Sexpr(sexpr);
+    };
+    match fun.name {
+        "eval" => fold_constants(Value::Sexpr(Sexpr(q.clone())), env),
+        // The head is returned as is, so it can only replace the call if it evaluates to itself.
+        "head" => match q.front() {
+            Some(v @ (Value::Num(_) | Value::Qexpr(_) | Value::Map(_) | Value::Set(_))) => {
+                v.clone()
+            }
+            _ => Value::Sexpr(sexpr),
+        },
+        "tail" if !q.is_empty() => Value::Qexpr(Qexpr(q.clone().slice(1..))),
+        _ => Value::Sexpr(sexpr),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{parse, symbol::Symbol};
+
+    fn folded(program: &str) -> String {
+        fold(parse(program).unwrap(), &Environment::default()).to_string()
+    }
+
+    #[test]
+    fn folds_arithmetic() {
+        assert_eq!(folded("* 2 (+ 4 5) (/ 10 2) (-2) (- 1 2 3)"), "1080");
+    }
+
+    #[test]
+    fn folds_around_variables() {
+        assert_eq!(folded("+ x (* 2 3)"), "( + x 6 )");
+    }
+
+    #[test]
+    fn unwraps_single_elements() {
+        assert_eq!(folded("list (x) ((2))"), "( list x 2 )");
+    }
+
+    #[test]
+    fn keeps_division_by_zero() {
+        assert_eq!(folded("+ 1 (/ 10 0)"), "( + 1 ( / 10 0 ) )");
+    }
+
+    #[test]
+    fn keeps_quoted_code() {
+        assert_eq!(folded("list {+ 1 2}"), "( list { + 1 2 } )");
+    }
+
+    #[test]
+    fn folds_eval_of_literal() {
+        assert_eq!(folded("eval {+ 1 (eval {* 2 3})}"), "7");
+        assert_eq!(folded("eval {tail x}"), "( tail x )");
+    }
+
+    #[test]
+    fn folds_head_and_tail_of_literal() {
+        assert_eq!(folded("+ 1 (head {5 10 11 15})"), "6");
+        assert_eq!(folded("head {x 10}"), "( head { x 10 } )");
+        assert_eq!(folded("tail {x 10}"), "{ 10 }");
+        assert_eq!(folded("head {}"), "( head {} )");
+    }
+
+    #[test]
+    fn respects_redefinitions() {
+        let mut env = Environment::default();
+        let minus = env.get(Symbol::new("-")).unwrap().clone();
+        env.define(Symbol::new("+"), minus);
+        assert_eq!(fold(parse("+ 1 2").unwrap(), &env).to_string(), "( + 1 2 )");
+    }
+
+    #[test]
+    fn keeps_programs_that_rebind() {
+        assert_eq!(
+            folded("list (redef {+} -) (+ 5 2)"),
+            "( list ( redef { + } - ) ( + 5 2 ) )"
+        );
+        assert_eq!(
+            folded("list (eval {def {x} 1}) (* 2 3)"),
+            "( list ( eval { def { x } 1 } ) ( * 2 3 ) )"
+        );
+
+        let mut env = Environment::default();
+        let Value::Sexpr(Sexpr(program)) = parse("redef {+} -").unwrap() else {
+            unreachable!()
+        };
+        env.define(Symbol::new("f"), Value::Qexpr(Qexpr(program)));
+        assert_eq!(
+            fold(parse("list (eval f) (+ 5 2)").unwrap(), &env).to_string(),
+            "( list ( eval f ) ( + 5 2 ) )"
+        );
+    }
+}
@@ -0,0 +1,203 @@
+use super::Error;
+use crate::{map::Map, qexpr::Qexpr, set::Set, sexpr::Sexpr, value::Value};
+use im::{ordmap, vector, Vector};
+use serde::{
+    de::{self, IntoDeserializer, Visitor},
+    forward_to_deserialize_any,
+};
+
+fn invalid(value: &Value, expected: &str) -> Error {
+    Error(format!("expected {expected}, found '{value}'"))
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Value {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Value {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            Self::Num(n) => visitor.visit_i64(n),
+            Self::Sym(s) => visitor.visit_str(s.as_str()),
+            Self::Str(s) => visitor.visit_string(s),
+            Self::Sexpr(Sexpr(s)) if s.is_empty() => visitor.visit_unit(),
+            Self::Qexpr(Qexpr(q)) => visitor.visit_seq(Seq(q.into_iter())),
+            Self::Set(Set(s)) => {
+                visitor.visit_seq(Seq(s.into_iter().collect::<Vector<_>>().into_iter()))
+            }
+            Self::Map(Map(m)) => visitor.visit_map(Entries {
+                entries: m.into_iter(),
+                value: None,
+            }),
+            v @ (Self::Sexpr(_) | Self::Fun(_)) => Err(invalid(&v, "data")),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            Self::Num(0) => visitor.visit_bool(false),
+            Self::Num(1) => visitor.visit_bool(true),
+            Self::Sym(s) if s.as_str() == "false" => visitor.visit_bool(false),
+            Self::Sym(s) if s.as_str() == "true" => visitor.visit_bool(true),
+            v => Err(invalid(&v, "0 or 1")),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            Self::Qexpr(Qexpr(q)) if q.is_empty() => visitor.visit_none(),
+            Self::Qexpr(Qexpr(mut q)) if q.len() == 1 => visitor.visit_some(q.remove(0)),
+            v => Err(invalid(&v, "{} or a q-expression of one value")),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self {
+            Self::Sym(name) => visitor.visit_enum(Variant {
+                name: Self::Sym(name),
+                fields: vector![],
+            }),
+            Self::Qexpr(Qexpr(mut q)) if matches!(q.front(), Some(Self::Sym(_))) => {
+                let name = q.pop_front().unwrap();
+                visitor.visit_enum(Variant { name, fields: q })
+            }
+            v => Err(invalid(&v, "a variant")),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct Seq(im::vector::ConsumingIter<Value>);
+
+impl<'de> de::SeqAccess<'de> for Seq {
+    type Error = Error;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        self.0.next().map(|v| seed.deserialize(v)).transpose()
+    }
+}
+
+struct Entries {
+    entries: ordmap::ConsumingIter<(crate::map::Key, Value)>,
+    value: Option<Value>,
+}
+
+impl<'de> de::MapAccess<'de> for Entries {
+    type Error = Error;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        let Some((key, value)) = self.entries.next() else {
+            return Ok(None);
+        };
+        self.value = Some(value);
+        seed.deserialize(Value::from(key)).map(Some)
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let value = self
+            .value
+            .take()
+            .ok_or_else(|| Error("map key without a value".to_string()))?;
+        seed.deserialize(value)
+    }
+}
+
+/// An enum variant: its name, and the fields that followed it in a q-expression.
+struct Variant {
+    name: Value,
+    fields: Vector<Value>,
+}
+
+impl<'de> de::EnumAccess<'de> for Variant {
+    type Error = Error;
+    type Variant = Fields;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Fields), Error> {
+        let variant = seed.deserialize(self.name.clone())?;
+        Ok((
+            variant,
+            Fields {
+                name: self.name,
+                fields: self.fields,
+            },
+        ))
+    }
+}
+
+struct Fields {
+    name: Value,
+    fields: Vector<Value>,
+}
+
+impl Fields {
+    /// The only field of a newtype or struct variant.
+    fn single(mut self) -> Result<Value, Error> {
+        match self.fields.len() {
+            1 => Ok(self.fields.remove(0)),
+            n => Err(Error(format!(
+                "variant '{}' takes one value, found {n}",
+                self.name
+            ))),
+        }
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Fields {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        if self.fields.is_empty() {
+            Ok(())
+        } else {
+            Err(Error(format!("variant '{}' takes no values", self.name)))
+        }
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self.single()?)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_seq(Seq(self.fields.into_iter()))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_any(self.single()?, visitor)
+    }
+}
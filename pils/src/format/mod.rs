@@ -0,0 +1,150 @@
+//! Pils syntax as a serde data format, for example to write configuration in pils:
+//!
+//! ```
+//! #[derive(serde_derive::Deserialize)]
+//! struct Config {
+//!     name: String,
+//!     ports: Vec<u16>,
+//! }
+//!
+//! let config: Config = pils::from_str(r#"#{ name "web" ports {80 443} }"#).unwrap();
+//! assert_eq!(config.ports, [80, 443]);
+//! ```
+//!
+//! Data is laid out like the conversions in [`crate::convert`]: structs are maps keyed by field name,
+//! sequences and tuples are q-expressions, `None` is `{}` and `Some(x)` is `{x}`.
+//! Unit variants are symbols and other variants q-expressions starting with the variant name.
+//! Strings are written as string literals, but symbols are read as strings, too.
+//! Booleans are 1 and 0, and the unit is `()`. Floats are not supported.
+//! Nothing is evaluated: the text is read as a single value.
+
+use crate::value::Value;
+use serde::{de::DeserializeOwned, Serialize};
+
+mod de;
+mod ser;
+
+/// An error reading or writing pils syntax.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error(String);
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl serde::ser::Error for Error {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+impl serde::de::Error for Error {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+/// Reads a `T` from pils source.
+pub fn from_str<T: DeserializeOwned>(input: &str) -> Result<T, Error> {
+    let value = input
+        .parse::<Value>()
+        .map_err(|e| Error(format!("{e:#}")))?;
+    T::deserialize(value)
+}
+
+/// Writes `value` as pils source.
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String, Error> {
+    Ok(value.serialize(ser::Serializer)?.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_derive::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Service {
+        name: String,
+        port: u16,
+        enabled: bool,
+        tags: Vec<String>,
+        limit: Option<u32>,
+        backend: Backend,
+        env: BTreeMap<String, i64>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Backend {
+        Memory,
+        Disk(String),
+        Remote { host: String, retries: (u8, u8) },
+    }
+
+    #[test]
+    fn round_trips() {
+        let service = Service {
+            name: "web app".to_string(),
+            port: 8080,
+            enabled: true,
+            tags: vec!["a".to_string(), "b".to_string()],
+            limit: None,
+            backend: Backend::Remote {
+                host: "db".to_string(),
+                retries: (1, 3),
+            },
+            env: BTreeMap::from([("depth".to_string(), 2)]),
+        };
+        let text = to_string(&service).unwrap();
+        assert_eq!(
+            text,
+            r#"#{ backend { Remote #{ host "db" retries { 1 3 } } } enabled 1 env #{ depth 2 } limit {} name "web app" port 8080 tags { "a" "b" } }"#
+        );
+        assert_eq!(from_str::<Service>(&text).unwrap(), service);
+    }
+
+    #[test]
+    fn reads_handwritten_source() {
+        let service: Service = from_str(
+            "#{ name web port 80 enabled 0 tags {}\n  limit {5} backend {Disk data} env #{} }",
+        )
+        .unwrap();
+        assert_eq!(service.name, "web");
+        assert_eq!(service.limit, Some(5));
+        assert_eq!(service.backend, Backend::Disk("data".to_string()));
+        assert_eq!(from_str::<Backend>("Memory").unwrap(), Backend::Memory);
+        assert_eq!(from_str::<()>("()").unwrap(), ());
+    }
+
+    #[test]
+    fn round_trips_string_keys() {
+        let map = BTreeMap::from([
+            ("my key".to_string(), 1),
+            ("42".to_string(), 2),
+            (String::new(), 3),
+            ("plain".to_string(), 4),
+        ]);
+        let text = to_string(&map).unwrap();
+        assert_eq!(text, r#"#{ plain 4 "" 3 "42" 2 "my key" 1 }"#);
+        assert_eq!(from_str::<BTreeMap<String, i64>>(&text).unwrap(), map);
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert_eq!(
+            from_str::<u8>("300").unwrap_err().to_string(),
+            "invalid value: integer `300`, expected u8"
+        );
+        assert_eq!(
+            from_str::<Backend>("{Tape 1}").unwrap_err().to_string(),
+            "unknown variant `Tape`, expected one of `Memory`, `Disk`, `Remote`"
+        );
+        assert!(from_str::<Service>("#{ name web }").is_err());
+        assert!(from_str::<u8>("{1").is_err());
+        assert!(to_string(&1.5).is_err());
+    }
+}
@@ -0,0 +1,351 @@
+use super::Error;
+use crate::{
+    map::{Key, Map},
+    qexpr::Qexpr,
+    sexpr::Sexpr,
+    symbol::Symbol,
+    value::Value,
+};
+use im::{OrdMap, Vector};
+use serde::{ser, Serialize};
+
+/// Serializes Rust data into a value.
+pub(super) struct Serializer;
+
+fn unit() -> Value {
+    Value::Sexpr(Sexpr(Vector::new()))
+}
+
+/// A q-expression starting with the variant name, like `{Disk data}`.
+fn variant(name: &str, fields: impl IntoIterator<Item = Value>) -> Value {
+    Value::Qexpr(Qexpr(
+        std::iter::once(Value::Sym(Symbol::new(name)))
+            .chain(fields)
+            .collect(),
+    ))
+}
+
+impl ser::Serializer for Serializer {
+    type Ok = Value;
+    type Error = Error;
+    type SerializeSeq = Seq;
+    type SerializeTuple = Seq;
+    type SerializeTupleStruct = Seq;
+    type SerializeTupleVariant = Seq;
+    type SerializeMap = Entries;
+    type SerializeStruct = Entries;
+    type SerializeStructVariant = Entries;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, Error> {
+        Ok(Value::Num(i64::from(v)))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, Error> {
+        Ok(Value::Num(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, Error> {
+        i64::try_from(v)
+            .map(Value::Num)
+            .map_err(|_| Error(format!("{v} does not fit into a number")))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, Error> {
+        self.serialize_f64(f64::from(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, Error> {
+        Err(Error(format!(
+            "cannot serialize {v}, floats are not supported"
+        )))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, Error> {
+        Ok(Value::Str(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, Error> {
+        Ok(Value::Str(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, Error> {
+        Ok(Value::Qexpr(
+            v.iter().map(|b| Value::Num(i64::from(*b))).collect(),
+        ))
+    }
+
+    fn serialize_none(self) -> Result<Value, Error> {
+        Ok(Value::Qexpr(Qexpr(Vector::new())))
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, Error> {
+        Ok(Value::Qexpr(Qexpr::from_iter([value.serialize(self)?])))
+    }
+
+    fn serialize_unit(self) -> Result<Value, Error> {
+        Ok(unit())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, Error> {
+        Ok(unit())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Value, Error> {
+        Ok(Value::Sym(Symbol::new(variant)))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        Ok(variant(name, [value.serialize(self)?]))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Seq, Error> {
+        Ok(Seq::default())
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Seq, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<Seq, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Seq, Error> {
+        Ok(Seq {
+            variant: Some(variant),
+            ..Seq::default()
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Entries, Error> {
+        Ok(Entries::default())
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Entries, Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Entries, Error> {
+        Ok(Entries {
+            variant: Some(variant),
+            ..Entries::default()
+        })
+    }
+}
+
+/// The elements of a sequence, tuple or tuple variant.
+#[derive(Default)]
+pub(super) struct Seq {
+    elements: Vector<Value>,
+    variant: Option<&'static str>,
+}
+
+impl Seq {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.elements.push_back(value.serialize(Serializer)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Value {
+        match self.variant {
+            Some(name) => variant(name, self.elements),
+            None => Value::Qexpr(Qexpr(self.elements)),
+        }
+    }
+}
+
+impl ser::SerializeSeq for Seq {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTuple for Seq {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTupleStruct for Seq {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTupleVariant for Seq {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(self.finish())
+    }
+}
+
+/// The entries of a map, struct or struct variant.
+#[derive(Default)]
+pub(super) struct Entries {
+    entries: OrdMap<Key, Value>,
+    next_key: Option<Key>,
+    variant: Option<&'static str>,
+}
+
+impl Entries {
+    fn insert<T: Serialize + ?Sized>(&mut self, key: Key, value: &T) -> Result<(), Error> {
+        self.entries.insert(key, value.serialize(Serializer)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Value {
+        let map = Value::Map(Map(self.entries));
+        match self.variant {
+            Some(name) => variant(name, [map]),
+            None => map,
+        }
+    }
+}
+
+/// Strings that are written like symbols become symbol keys, like `#{ depth 2 }`.
+fn key<T: Serialize + ?Sized>(key: &T) -> Result<Key, Error> {
+    match key.serialize(Serializer)? {
+        Value::Num(n) => Ok(Key::Num(n)),
+        Value::Sym(s) => Ok(Key::Sym(s)),
+        Value::Str(s) => Ok(Key::from_str_key(&s)),
+        v => Err(Error(format!("'{v}' cannot be used as a map key"))),
+    }
+}
+
+impl ser::SerializeMap for Entries {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, k: &T) -> Result<(), Error> {
+        self.next_key = Some(key(k)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self
+            .next_key
+            .take()
+            .ok_or_else(|| Error("map value without a key".to_string()))?;
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeStruct for Entries {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.insert(Key::Sym(Symbol::new(name)), value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeStructVariant for Entries {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.insert(Key::Sym(Symbol::new(name)), value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(self.finish())
+    }
+}
@@ -1,16 +1,311 @@
-use crate::{environment::Environment, value::Value};
+use crate::{
+    convert::{FromValue, IntoValue},
+    environment::Environment,
+    symbol::Symbol,
+    value::Value,
+};
+use anyhow::Context;
+use serde::{de, Deserializer, Serializer};
+use serde_derive::Deserialize;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, LazyLock};
 
+/// What builtins do: they are passed their evaluated arguments as an s-expression.
+pub type Builtin = dyn Fn(Value, &mut Environment) -> Result<Value, anyhow::Error> + Send + Sync;
+
+/// How many arguments a builtin takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+    Exactly(usize),
+    AtLeast(usize),
+}
+
+impl Arity {
+    pub(crate) fn accepts(self, n: usize) -> bool {
+        match self {
+            Self::Exactly(m) => n == m,
+            Self::AtLeast(m) => n >= m,
+        }
+    }
+}
+
+impl std::fmt::Display for Arity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Exactly(1) => write!(f, "1 argument"),
+            Self::Exactly(n) => write!(f, "{n} arguments"),
+            Self::AtLeast(1) => write!(f, "at least 1 argument"),
+            Self::AtLeast(n) => write!(f, "at least {n} arguments"),
+        }
+    }
+}
+
+/// A builtin function, either one of pils or one registered by an embedder.
+/// Builtins are identified by their name, since closures cannot be compared.
 #[derive(Clone)]
-pub struct Function(pub(crate) fn(Value, &mut Environment) -> Result<Value, anyhow::Error>);
+pub struct Function {
+    pub(crate) name: &'static str,
+    fun: Arc<Builtin>,
+    arity: Option<Arity>,
+    /// Whether calls fail if they do not match the arity, which otherwise only informs tools
+    /// like the linter.
+    checks_arity: bool,
+    doc: Option<Arc<str>>,
+}
+
+impl Function {
+    pub fn new(
+        name: &str,
+        fun: impl Fn(Value, &mut Environment) -> Result<Value, anyhow::Error> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name: Symbol::new(name).as_str(),
+            fun: Arc::new(fun),
+            arity: None,
+            checks_arity: false,
+            doc: None,
+        }
+    }
+
+    /// A builtin taking and returning Rust types, like
+    /// `Function::typed("add", |(a, b): (i64, i64)| Ok(a + b))`.
+    /// The arguments are converted as a tuple or `Vec`.
+    pub fn typed<A: FromValue, R: IntoValue>(
+        name: &str,
+        fun: impl Fn(A) -> Result<R, anyhow::Error> + Send + Sync + 'static,
+    ) -> Self {
+        let name = Symbol::new(name).as_str();
+        Self::new(name, move |args, _env| {
+            let args = A::from_value(args)
+                .with_context(|| format!("Function '{name}' passed incorrect type"))?;
+            fun(args).map(IntoValue::into_value)
+        })
+    }
+
+    /// Makes calls with a different number of arguments fail before reaching the builtin.
+    #[must_use]
+    pub fn with_arity(mut self, arity: Arity) -> Self {
+        self.arity = Some(arity);
+        self.checks_arity = true;
+        self
+    }
+
+    /// Declares the arity for the linter only. The builtins of pils ignore extra arguments,
+    /// like `head {1 2} 3`, so their calls are not checked.
+    #[must_use]
+    pub(crate) fn with_unchecked_arity(mut self, arity: Arity) -> Self {
+        self.arity = Some(arity);
+        self
+    }
+
+    #[must_use]
+    pub fn with_doc(mut self, doc: &str) -> Self {
+        self.doc = Some(doc.into());
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        self.name
+    }
+
+    pub fn arity(&self) -> Option<Arity> {
+        self.arity
+    }
+
+    pub fn doc(&self) -> Option<&str> {
+        self.doc.as_deref()
+    }
+
+    pub(crate) fn call(&self, args: Value, env: &mut Environment) -> Result<Value, anyhow::Error> {
+        if let (Some(arity), true, Value::Sexpr(s)) = (self.arity, self.checks_arity, &args) {
+            if !arity.accepts(s.0.len()) {
+                return Err(anyhow::anyhow!(
+                    "'{}' takes {arity}, but got {}",
+                    self.name,
+                    s.0.len()
+                ));
+            }
+        }
+        (self.fun)(args, env)
+    }
+}
+
+impl std::fmt::Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<builtin {}>", self.name)
+    }
+}
 
 impl std::fmt::Debug for Function {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "<function>")
+        write!(f, "<builtin {}>", self.name)
+    }
+}
+
+/// Functions are serialized by name, like `{"Builtin":"head"}`, and resolved through a [`Registry`].
+impl serde::Serialize for Function {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_variant("Function", 0, "Builtin", self.name)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Function {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        enum Reference {
+            Builtin(String),
+        }
+
+        let Reference::Builtin(name) = Reference::deserialize(deserializer)?;
+        Registry::resolve(&name)
+            .ok_or_else(|| de::Error::custom(format!("unknown builtin '{name}'")))
+    }
+}
+
+/// Builtins by name, which deserialized functions are looked up in.
+/// Unless another registry is in [`Registry::scope`], these are the builtins of pils.
+#[derive(Debug, Clone, Default)]
+pub struct Registry(HashMap<&'static str, Function>);
+
+static STANDARD: LazyLock<Registry> = LazyLock::new(|| Registry::of(&Environment::default()));
+
+thread_local! {
+    static SCOPED: RefCell<Option<Registry>> = const { RefCell::new(None) };
+}
+
+impl Registry {
+    /// The builtins of pils.
+    pub fn standard() -> Self {
+        STANDARD.clone()
+    }
+
+    /// The builtins of an environment, including functions registered by an embedder.
+    pub fn of(env: &Environment) -> Self {
+        Self(
+            env.bindings
+                .iter()
+                .filter_map(|(sym, value)| match value {
+                    Value::Fun(fun) if env.is_builtin(*sym, value) => Some((fun.name, fun.clone())),
+                    _ => None,
+                })
+                .collect(),
+        )
+    }
+
+    pub fn insert(&mut self, function: Function) {
+        self.0.insert(function.name, function);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Function> {
+        self.0.get(name)
+    }
+
+    pub fn functions(&self) -> impl Iterator<Item = &Function> {
+        self.0.values()
+    }
+
+    /// Runs `f`, resolving the functions deserialized by this thread in the meantime through this registry.
+    /// The previous registry is restored afterwards, even if `f` panics.
+    pub fn scope<R>(&self, f: impl FnOnce() -> R) -> R {
+        /// Puts back the registry that was in scope when dropped.
+        struct Restore(Option<Registry>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                SCOPED.with(|scoped| scoped.replace(self.0.take()));
+            }
+        }
+
+        let _restore = Restore(SCOPED.with(|scoped| scoped.replace(Some(self.clone()))));
+        f()
+    }
+
+    fn resolve(name: &str) -> Option<Function> {
+        SCOPED
+            .with(|scoped| scoped.borrow().as_ref().and_then(|r| r.get(name).cloned()))
+            .or_else(|| STANDARD.get(name).cloned())
     }
 }
 
 impl PartialEq for Function {
     fn eq(&self, other: &Self) -> bool {
-        self.0 as usize == other.0 as usize
+        self.name == other.name
+    }
+}
+
+impl Eq for Function {}
+
+impl PartialOrd for Function {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Function {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.name.cmp(other.name)
+    }
+}
+
+impl Hash for Function {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{parse, sexpr::Sexpr};
+
+    #[test]
+    fn serializes_by_name() {
+        let mut env = Environment::default();
+        let value = parse("list head 1").unwrap().eval(&mut env).unwrap();
+        assert_eq!(value.to_string(), "{ <builtin head> 1 }");
+        let json = serde_json::to_string(&value).unwrap();
+        assert_eq!(json, r#"{"Qexpr":[{"Fun":{"Builtin":"head"}},{"Num":1}]}"#);
+        assert_eq!(serde_json::from_str::<Value>(&json).unwrap(), value);
+
+        let env: Environment = serde_json::from_str(&serde_json::to_string(&env).unwrap()).unwrap();
+        assert!(matches!(env.get(Symbol::new("tail")), Some(Value::Fun(_))));
+    }
+
+    #[test]
+    fn resolves_through_scoped_registry() {
+        let json = r#"{"Fun":{"Builtin":"answer"}}"#;
+        assert_eq!(
+            serde_json::from_str::<Value>(json).unwrap_err().to_string(),
+            "unknown builtin 'answer'"
+        );
+        let mut registry = Registry::standard();
+        registry.insert(Function::new("answer", |_args, _env| Ok(Value::Num(42))));
+        let value = registry
+            .scope(|| serde_json::from_str::<Value>(json))
+            .unwrap();
+        let args = Value::Sexpr(Sexpr::from_iter([]));
+        let Value::Fun(answer) = value else {
+            unreachable!()
+        };
+        assert_eq!(
+            answer.call(args, &mut Environment::default()).unwrap(),
+            Value::Num(42)
+        );
+        assert!(serde_json::from_str::<Value>(json).is_err());
+    }
+
+    #[test]
+    fn restores_registry_after_panics() {
+        let mut registry = Registry::standard();
+        registry.insert(Function::new("answer", |_args, _env| Ok(Value::Num(42))));
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            registry.scope(|| panic!("in scope"))
+        }));
+        assert!(panicked.is_err());
+        let json = r#"{"Fun":{"Builtin":"answer"}}"#;
+        assert!(serde_json::from_str::<Value>(json).is_err());
     }
 }
@@ -0,0 +1,125 @@
+//! Images hold the definitions made in an interpreter, so that a session can be saved and restored.
+//!
+//! Builtins are not part of an image. Functions in definitions are saved by name, and
+//! linked to the builtins of the loading interpreter, see [`Registry`].
+
+use crate::{
+    environment::Environment, function::Registry, symbol::Symbol, types::Type, value::Value,
+};
+use anyhow::Context;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Image {
+    definitions: BTreeMap<String, Value>,
+    #[serde(default)]
+    declared: BTreeMap<String, Type>,
+    #[serde(default)]
+    constants: BTreeSet<String>,
+}
+
+pub(crate) fn save(env: &Environment) -> Result<String, anyhow::Error> {
+    let mut image = Image {
+        definitions: BTreeMap::new(),
+        declared: BTreeMap::new(),
+        constants: BTreeSet::new(),
+    };
+    for (sym, value) in env.definitions() {
+        image
+            .definitions
+            .insert(sym.as_str().to_string(), value.clone());
+        if let Some(ty) = env.declared.get(sym) {
+            image.declared.insert(sym.as_str().to_string(), *ty);
+        }
+        if env.is_constant(*sym) {
+            image.constants.insert(sym.as_str().to_string());
+        }
+    }
+    serde_json::to_string_pretty(&image).context("Failed to save image")
+}
+
+/// Defines everything in `image`, overwriting definitions of the same names.
+pub(crate) fn load(image: &str, env: &mut Environment) -> Result<(), anyhow::Error> {
+    let image: Image = Registry::of(env)
+        .scope(|| serde_json::from_str(image))
+        .context("Failed to read image")?;
+    let definitions = image
+        .definitions
+        .into_iter()
+        .map(|(name, value)| (Symbol::new(&name), value))
+        .collect::<Vec<_>>();
+    env.reserve_definitions(definitions.iter().map(|(sym, value)| (*sym, value)))?;
+    for (sym, value) in definitions {
+        env.define(sym, value);
+    }
+    for (name, ty) in image.declared {
+        env.declared.insert(Symbol::new(&name), ty);
+    }
+    env.constants
+        .extend(image.constants.iter().map(|name| Symbol::new(name)));
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn load_error(image: &str, env: &mut Environment) -> String {
+        format!("{:#}", load(image, env).unwrap_err())
+    }
+
+    #[test]
+    fn rejects_malformed_images() {
+        let mut env = Environment::default();
+        assert_eq!(
+            load_error("{", &mut env),
+            "Failed to read image: EOF while parsing an object at line 1 column 1"
+        );
+        assert_eq!(
+            load_error(r#"{"definitions": {"x": {"Num": "one"}}}"#, &mut env),
+            r#"Failed to read image: invalid type: string "one", expected i64 at line 1 column 35"#
+        );
+        assert_eq!(
+            load_error(r#"{"declared": {}}"#, &mut env),
+            "Failed to read image: missing field `definitions` at line 1 column 16"
+        );
+        assert!(env.get(Symbol::new("x")).is_none());
+    }
+
+    #[test]
+    fn loads_nothing_from_failing_images() {
+        let mut env = Environment::default();
+        let image = r#"{"definitions": {"a": {"Num": 1}, "b": {"Fun": {"Builtin": "nope"}}}}"#;
+        assert_eq!(
+            load_error(image, &mut env),
+            "Failed to read image: unknown builtin 'nope' at line 1 column 67"
+        );
+        assert!(env.get(Symbol::new("a")).is_none());
+    }
+
+    #[test]
+    fn loads_nothing_beyond_the_memory_limit() {
+        let mut env = Environment::default();
+        env.config.memory_limit = Some(env.memory + 4 * std::mem::size_of::<Value>());
+        let image = r#"{"definitions": {"a": {"Num": 1}, "b": {"Qexpr": [{"Num": 1}, {"Num": 2}, {"Num": 3}, {"Num": 4}]}}}"#;
+        assert_eq!(
+            load_error(image, &mut env),
+            format!(
+                "Out of memory, the limit is {} bytes",
+                env.config.memory_limit.unwrap()
+            )
+        );
+        assert!(env.get(Symbol::new("a")).is_none());
+        load(r#"{"definitions": {"a": {"Num": 1}}}"#, &mut env).unwrap();
+        load(r#"{"definitions": {"a": {"Num": 2}}}"#, &mut env).unwrap();
+    }
+
+    #[test]
+    fn loads_images_without_optional_fields() {
+        let mut env = Environment::default();
+        load(r#"{"definitions": {"a": {"Num": 1}}}"#, &mut env).unwrap();
+        assert_eq!(env.get(Symbol::new("a")), Some(&Value::Num(1)));
+        assert!(!env.is_constant(Symbol::new("a")));
+    }
+}
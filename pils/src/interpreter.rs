@@ -0,0 +1,628 @@
+use crate::{
+    config::Config,
+    convert::{FromValue, IntoArgs},
+    environment::Environment,
+    fold,
+    function::Function,
+    image,
+    interrupt::CancellationToken,
+    lint::Linter,
+    parse,
+    qexpr::Qexpr,
+    sexpr::Sexpr,
+    symbol::Symbol,
+    types,
+    value::Value,
+};
+use anyhow::{anyhow, Context, Error};
+use itertools::Itertools;
+use std::{
+    collections::HashMap,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// An interpreter with its own definitions and configuration.
+/// Interpreters are independent of each other.
+#[derive(Debug, Default)]
+pub struct Interpreter {
+    env: Environment,
+}
+
+/// A clone starts with the same definitions and configuration, but has its own
+/// [`CancellationToken`], so that cancelling one does not stop the other.
+impl Clone for Interpreter {
+    fn clone(&self) -> Self {
+        let mut env = self.env.clone();
+        env.cancellation = CancellationToken::default();
+        Self { env }
+    }
+}
+
+impl Interpreter {
+    pub fn new(config: Config) -> Self {
+        let mut interpreter = Self::default();
+        interpreter.configure(config);
+        interpreter
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.env.config
+    }
+
+    pub fn configure(&mut self, config: Config) {
+        self.env.configure(config);
+    }
+
+    /// A token that cancels the evaluations of this interpreter, also from other threads.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.env.cancellation.clone()
+    }
+
+    /// The estimated memory taken by definitions and the symbols interned for them, in bytes, which counts against [`Config::memory_limit`].
+    /// It is only tracked while a memory limit is set, and is 0 otherwise.
+    pub fn memory_usage(&self) -> usize {
+        self.env.memory
+    }
+
+    /// A linter that knows the definitions of this interpreter and its builtins,
+    /// including the arities of registered ones.
+    pub fn linter(&self) -> Linter {
+        Linter::of(&self.env)
+    }
+
+    /// The evaluation steps taken by the last program, which count against [`Config::fuel`].
+    pub fn steps(&self) -> u64 {
+        self.env.steps
+    }
+
+    /// Adds a builtin implemented in Rust, which may be a closure capturing state.
+    /// Builtins are constant, so names that are already taken by builtins cannot be registered.
+    pub fn register(
+        &mut self,
+        name: &str,
+        fun: impl Fn(Value, &mut Environment) -> Result<Value, Error> + Send + Sync + 'static,
+    ) -> Result<(), Error> {
+        self.register_function(Function::new(name, fun))
+    }
+
+    /// Adds a builtin along with its metadata, like
+    /// `Function::new("double", double).with_arity(Arity::Exactly(1)).with_doc("Doubles a number")`.
+    pub fn register_function(&mut self, function: Function) -> Result<(), Error> {
+        if self.env.is_constant(Symbol::new(function.name)) {
+            return Err(anyhow!(
+                "'{}' is constant and cannot be registered",
+                function.name
+            ));
+        }
+        self.env.add_function(function);
+        Ok(())
+    }
+
+    /// The documentation of the builtin bound to `name`, if it has any.
+    pub fn doc(&self, name: &str) -> Option<&str> {
+        match self.env.get(Symbol::new(name))? {
+            Value::Fun(fun) => fun.doc(),
+            _ => None,
+        }
+    }
+
+    /// Applies the builtin bound to `name` to `args`, as if evaluating `(name args...)`,
+    /// and converts the result, like `let n: i64 = pils.call("+", (1, 2))?`.
+    /// If `name` is bound to a q-expression, like `inc` after `def {inc} {+ 1}`, that is evaluated
+    /// as code with the arguments appended, so `pils.call("inc", (41,))` returns 42.
+    /// Arguments are passed as they are, without evaluating them.
+    /// Like [`Self::process`], the call gets all of the fuel and fails if it was cancelled.
+    pub fn call<R: FromValue>(&mut self, name: &str, args: impl IntoArgs) -> Result<R, Error> {
+        let binding = self
+            .env
+            .get(Symbol::new(name))
+            .with_context(|| format!("unbound symbol '{name}'"))?
+            .clone();
+        let args = args.into_args();
+        let result = self.env.metered(|env| {
+            env.start()?;
+            let head = match binding {
+                Value::Qexpr(Qexpr(code)) => {
+                    env.step()?;
+                    env.nested(|env| {
+                        code.into_iter()
+                            .map(|v| v.eval(env))
+                            .collect::<Result<im::Vector<_>, Error>>()
+                    })?
+                }
+                fun => im::Vector::unit(fun),
+            };
+            Sexpr(head.into_iter().chain(args).collect()).apply(env)
+        })?;
+        R::from_value(result).with_context(|| format!("in result of '{name}'"))
+    }
+
+    pub fn process(&mut self, input: &str) -> Result<Value, Error> {
+        self.env
+            .metered(|env| parse(input).and_then(|val| evaluate(val, env)))
+    }
+
+    /// Like [`Self::process`], but fails with [`crate::Interrupt::TimedOut`] once `timeout` has passed.
+    /// The timeout is checked between evaluation steps, so a single builtin that runs for long,
+    /// like one registered by an embedder, cannot be interrupted and may overrun it.
+    pub fn process_with_timeout(&mut self, input: &str, timeout: Duration) -> Result<Value, Error> {
+        self.env.deadline = Some((Instant::now() + timeout, timeout));
+        let result = self.process(input);
+        self.env.deadline = None;
+        result
+    }
+
+    /// Evaluates a file, one program per line, so that it may import modules next to it.
+    /// Returns the value of the last program.
+    pub fn process_file(&mut self, path: &Path) -> Result<Value, Error> {
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let file = self.env.file.replace(path.to_path_buf());
+        let result = self.env.metered(|env| evaluate_source(&source, env));
+        self.env.file = file;
+        result
+    }
+
+    /// Saves the definitions made so far, but not the builtins, to a file.
+    pub fn save_image(&self, path: &Path) -> Result<(), Error> {
+        std::fs::write(path, self.save_image_str()?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Restores the definitions saved in a file, overwriting definitions of the same names.
+    pub fn load_image(&mut self, path: &Path) -> Result<(), Error> {
+        let image = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        self.load_image_str(&image)
+    }
+
+    pub fn save_image_str(&self) -> Result<String, Error> {
+        image::save(&self.env)
+    }
+
+    pub fn load_image_str(&mut self, image: &str) -> Result<(), Error> {
+        self.env.metered(|env| image::load(image, env))
+    }
+
+    #[must_use]
+    pub fn process_str(&mut self, line: &str) -> String {
+        match self.process(line.trim()) {
+            Ok(v) => format!("{v}"),
+            Err(e) => format!("Error: {e}"),
+        }
+    }
+
+    /// The definitions other than builtins, as JSON.
+    #[must_use]
+    pub fn get_env_json(&self) -> String {
+        let env: HashMap<&Symbol, &Value> = self.env.definitions().collect();
+        serde_json::to_string_pretty(&env)
+            .context("Serialization fault")
+            .unwrap()
+    }
+
+    /// The definitions other than builtins, as JSON with their values displayed as strings.
+    #[must_use]
+    pub fn get_env_tuples(&self) -> String {
+        let env: HashMap<&Symbol, String> = self
+            .env
+            .definitions()
+            .map(|(k, v)| (k, format!("{v}")))
+            .collect();
+        serde_json::to_string_pretty(&env)
+            .context("Serialization fault")
+            .unwrap()
+    }
+}
+
+/// Evaluates source code, one program per line. Empty lines and lines starting with ';' are skipped.
+pub(crate) fn evaluate_source(source: &str, env: &mut Environment) -> Result<Value, Error> {
+    let mut result = Value::Sexpr(Sexpr(im::Vector::new()));
+    for (n, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        result = parse(line)
+            .and_then(|val| evaluate(val, env))
+            .with_context(|| format!("line {}", n + 1))?;
+    }
+    Ok(result)
+}
+
+fn evaluate(val: Value, env: &mut Environment) -> Result<Value, Error> {
+    env.start()?;
+    if env.config.check_types {
+        types::check(&val, env)
+            .map_err(|mismatches| anyhow!("Type error: {}", mismatches.iter().join("; ")))?;
+    }
+    let val = if env.config.fold_constants {
+        fold::fold(val, env)
+    } else {
+        val
+    };
+    Value::run(val, env)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interrupt::Interrupt;
+    use std::sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc,
+    };
+
+    #[test]
+    fn interpreters_are_independent() {
+        let mut first = Interpreter::default();
+        let mut second = Interpreter::default();
+        assert_eq!(first.process_str("def {x} 1"), "()");
+        assert_eq!(first.process_str("x"), "1");
+        assert_eq!(second.process_str("x"), "Error: unbound symbol");
+        assert_eq!(second.get_env_json(), "{}");
+    }
+
+    #[test]
+    fn registers_closures() {
+        use crate::function::Arity;
+
+        let mut pils = Interpreter::default();
+        let calls = Arc::new(AtomicI64::new(0));
+        let counter = calls.clone();
+        pils.register("count", move |_args, _env| {
+            Ok(Value::Num(counter.fetch_add(1, Ordering::Relaxed) + 1))
+        })
+        .unwrap();
+        let double = Function::new("double", |args, _env| match args {
+            Value::Sexpr(Sexpr(s)) => match s.front() {
+                Some(Value::Num(n)) => Ok(Value::Num(2 * n)),
+                _ => Err(anyhow!("'double' on non-number")),
+            },
+            _ => Err(anyhow!("non-sexpr passed to 'double'")),
+        })
+        .with_arity(Arity::Exactly(1))
+        .with_doc("Doubles a number.");
+        pils.register_function(double).unwrap();
+
+        assert_eq!(pils.process_str("+ (count 1) (count 2)"), "3");
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+        assert_eq!(pils.process_str("double (double 3)"), "12");
+        assert_eq!(
+            pils.process_str("double 1 2"),
+            "Error: 'double' takes 1 argument, but got 2"
+        );
+        assert_eq!(pils.doc("double"), Some("Doubles a number."));
+        assert_eq!(pils.doc("count"), None);
+        assert_eq!(pils.linter().lint("double 1 2").unwrap().len(), 1);
+        assert!(pils.register("+", |args, _env| Ok(args)).is_err());
+    }
+
+    #[test]
+    fn registers_typed_functions() {
+        let mut pils = Interpreter::default();
+        let repeat = Function::typed("repeat", |(n, x): (usize, i64)| Ok(vec![x; n]));
+        pils.register_function(repeat).unwrap();
+        let total = Function::typed("total", |xs: Vec<i64>| Ok(xs.iter().sum::<i64>()));
+        pils.register_function(total).unwrap();
+        assert_eq!(pils.process_str("repeat 3 7"), "{ 7 7 7 }");
+        assert_eq!(pils.process_str("total 1 2 3"), "6");
+        assert_eq!(
+            pils.process_str("repeat -1 7"),
+            "Error: Function 'repeat' passed incorrect type"
+        );
+    }
+
+    #[test]
+    fn calls_functions() {
+        let mut pils = Interpreter::default();
+        pils.process("def {xs double} {1 2 3} (+ 1 1)").unwrap();
+        pils.register_function(Function::typed("twice", |(n,): (i64,)| Ok(2 * n)))
+            .unwrap();
+        assert_eq!(pils.call::<i64>("+", (1, 2_u8, 3)).unwrap(), 6);
+        assert_eq!(pils.call::<i64>("twice", (21,)).unwrap(), 42);
+        assert_eq!(
+            pils.call::<Vec<i64>>("tail", [Value::qexpr([Value::num(1), Value::num(2)])])
+                .unwrap(),
+            vec![2]
+        );
+        pils.process("def {inc pair} {+ 1} {list}").unwrap();
+        assert_eq!(pils.call::<i64>("inc", (41,)).unwrap(), 42);
+        assert_eq!(pils.call::<Vec<i64>>("pair", (1, 2)).unwrap(), vec![1, 2]);
+        assert_eq!(
+            pils.call::<Vec<u8>>("xs", ()).unwrap_err().to_string(),
+            "First element is not a function"
+        );
+        assert_eq!(
+            pils.call::<i64>("missing", ()).unwrap_err().to_string(),
+            "unbound symbol 'missing'"
+        );
+        assert_eq!(
+            format!("{:#}", pils.call::<String>("+", (1, 2)).unwrap_err()),
+            "in result of '+': expected a symbol or string, found '3'"
+        );
+        assert_eq!(
+            pils.call::<i64>("double", (1,)).unwrap_err().to_string(),
+            "First element is not a function"
+        );
+    }
+
+    #[test]
+    fn restores_images() {
+        let mut pils = Interpreter::default();
+        pils.register_function(Function::typed("twice", |(n,): (i64,)| Ok(2 * n)))
+            .unwrap();
+        pils.process("def {n:Num xs first} 1 {a \"b\"} head")
+            .unwrap();
+        pils.process("const {xs}").unwrap();
+        pils.process("redef {+} 5").unwrap();
+        pils.process("def {double fs} twice (list tail twice)")
+            .unwrap();
+        let image = pils.save_image_str().unwrap();
+        assert!(!image.contains("\"tail\":"));
+
+        let mut restored = Interpreter::default();
+        restored
+            .register_function(Function::typed("twice", |(n,): (i64,)| Ok(2 * n)))
+            .unwrap();
+        restored.load_image_str(&image).unwrap();
+        assert_eq!(restored.process_str("first xs"), "a");
+        assert_eq!(restored.process_str("double n"), "2");
+        assert_eq!(
+            restored.process_str("fs"),
+            "{ <builtin tail> <builtin twice> }"
+        );
+        assert_eq!(restored.process_str("+"), "5");
+        assert_eq!(
+            restored.process_str("def {xs} 1"),
+            "Error: 'xs' is constant, use 'redef' to redefine it"
+        );
+        assert_eq!(restored.env.declared[&Symbol::new("n")], types::Type::Num);
+
+        let mut without_twice = Interpreter::default();
+        assert_eq!(
+            format!("{:#}", without_twice.load_image_str(&image).unwrap_err()),
+            "Failed to read image: unknown builtin 'twice' at line 10 column 5"
+        );
+        assert_eq!(without_twice.process_str("first"), "Error: unbound symbol");
+    }
+
+    #[test]
+    fn folds_as_evaluated() {
+        for fold_constants in [false, true] {
+            let mut pils = Interpreter::new(Config {
+                fold_constants,
+                ..Config::default()
+            });
+            assert_eq!(pils.process_str("list (redef {+} -) (+ 5 2)"), "{ () -7 }");
+        }
+    }
+
+    #[test]
+    fn stops_when_out_of_fuel() {
+        for compile in [false, true] {
+            let mut pils = Interpreter::new(Config {
+                compile,
+                fold_constants: false,
+                fuel: Some(10),
+                ..Config::default()
+            });
+            assert_eq!(pils.process_str("+ 1 (* 2 3)"), "7");
+            assert_eq!(pils.steps(), 2);
+            pils.process("def {loop} {eval loop}").unwrap();
+            let error = pils.process("eval loop").unwrap_err();
+            assert_eq!(
+                error.downcast_ref::<Interrupt>(),
+                Some(&Interrupt::OutOfFuel { steps: 10 })
+            );
+            assert_eq!(error.to_string(), "Out of fuel after 10 steps");
+            assert_eq!(pils.process_str("+ 1 1"), "2");
+        }
+    }
+
+    #[test]
+    fn calls_with_limits() {
+        let mut pils = Interpreter::new(Config {
+            fuel: Some(3),
+            ..Config::default()
+        });
+        pils.process("def {loop} {eval loop}").unwrap();
+        assert!(pils.process("eval loop").is_err());
+        assert_eq!(pils.call::<i64>("+", (1, 2)).unwrap(), 3);
+
+        pils.cancellation_token().cancel();
+        let error = pils.call::<i64>("+", (1, 2)).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<Interrupt>(),
+            Some(&Interrupt::Cancelled)
+        );
+    }
+
+    #[test]
+    fn limits_depth() {
+        for compile in [false, true] {
+            let mut pils = Interpreter::new(Config {
+                compile,
+                max_depth: Some(20),
+                ..Config::default()
+            });
+            pils.process("def {loop} {eval loop}").unwrap();
+            let error = pils.process("eval loop").unwrap_err();
+            assert_eq!(
+                error.downcast_ref::<Interrupt>(),
+                Some(&Interrupt::TooDeep { depth: 20 })
+            );
+            assert_eq!(pils.process_str("eval {eval {+ 1 1}}"), "2");
+        }
+    }
+
+    #[test]
+    fn limits_memory() {
+        let mut pils = Interpreter::default();
+        assert_eq!(pils.memory_usage(), 0);
+        pils.configure(Config {
+            memory_limit: Some(1 << 30),
+            ..Config::default()
+        });
+        let builtins = pils.memory_usage();
+        assert!(builtins > 0);
+        pils.process("def {xs} {1 2 3}").unwrap();
+        assert_eq!(
+            pils.memory_usage(),
+            builtins + 4 * std::mem::size_of::<Value>()
+        );
+        pils.process("redef {xs} {1}").unwrap();
+        assert_eq!(
+            pils.memory_usage(),
+            builtins + 2 * std::mem::size_of::<Value>()
+        );
+
+        pils.configure(Config {
+            memory_limit: Some(builtins + 20 * std::mem::size_of::<Value>()),
+            ..Config::default()
+        });
+        assert_eq!(
+            pils.process_str("join {{1 2 3 4 5} {6 7 8 9}}"),
+            "{ 1 2 3 4 5 6 7 8 9 }"
+        );
+        let error = pils
+            .process("join {{1 2 3 4 5 6 7 8 9 10} {1 2 3 4 5 6 7 8 9 10}}")
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<Interrupt>(),
+            Some(Interrupt::OutOfMemory { .. })
+        ));
+        assert!(pils
+            .process_str("def {ys} {1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20}")
+            .starts_with("Error: Out of memory, the limit is"));
+        assert_eq!(pils.process_str("ys"), "Error: unbound symbol");
+
+        pils.configure(Config {
+            memory_limit: Some(pils.memory_usage() + std::mem::size_of::<Value>()),
+            ..Config::default()
+        });
+        assert_eq!(pils.process_str("redef {xs} {7}"), "()");
+        assert_eq!(pils.process_str("xs"), "{ 7 }");
+    }
+
+    #[test]
+    fn sizes_shared_values_within_the_limit() {
+        let mut pils = Interpreter::default();
+        pils.process("def {a} {1}").unwrap();
+        for _ in 0..64 {
+            pils.process("def {a} (list a a)").unwrap();
+        }
+
+        let limit = 1 << 20;
+        let mut pils = Interpreter::new(Config {
+            memory_limit: Some(limit),
+            ..Config::default()
+        });
+        pils.process("def {a} {1}").unwrap();
+        let error = (0..64)
+            .find_map(|_| pils.process("def {a} (list a a)").err())
+            .unwrap();
+        assert_eq!(
+            error.downcast_ref::<Interrupt>(),
+            Some(&Interrupt::OutOfMemory { limit })
+        );
+        assert!(pils.memory_usage() <= limit);
+    }
+
+    #[test]
+    fn charges_interned_symbols() {
+        let mut pils = Interpreter::new(Config {
+            memory_limit: Some(1 << 30),
+            ..Config::default()
+        });
+        let before = pils.memory_usage();
+        pils.process(r#"json-parse "{\"charged-symbol-1\": 1, \"charged-symbol-2\": 2}""#)
+            .unwrap();
+        assert!(pils.memory_usage() >= before + 2 * "charged-symbol-1".len());
+
+        pils.configure(Config {
+            memory_limit: Some(pils.memory_usage() + 100),
+            ..Config::default()
+        });
+        let keys = (0..20)
+            .map(|i| format!(r#"\"fresh-symbol-{i}\": {i}"#))
+            .collect::<Vec<_>>()
+            .join(", ");
+        assert_eq!(
+            pils.process_str(&format!(r#"json-parse "{{{keys}}}""#)),
+            format!(
+                "Error: Out of memory, the limit is {} bytes",
+                pils.config().memory_limit.unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn cancels_from_other_threads() {
+        let mut pils = Interpreter::default();
+        let naps = Arc::new(AtomicI64::new(0));
+        let counter = naps.clone();
+        pils.register("nap", move |_args, _env| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            std::thread::sleep(Duration::from_millis(5));
+            Ok(Value::Num(1))
+        })
+        .unwrap();
+        let program = format!("+ {}", "(nap 0) ".repeat(200));
+
+        let token = pils.cancellation_token();
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            token.cancel();
+        });
+        let error = pils.process(&program).unwrap_err();
+        canceller.join().unwrap();
+        assert_eq!(
+            error.downcast_ref::<Interrupt>(),
+            Some(&Interrupt::Cancelled)
+        );
+        assert!(naps.load(Ordering::Relaxed) < 200);
+        assert_eq!(pils.process_str("+ 1 1"), "Error: Evaluation cancelled");
+        pils.cancellation_token().reset();
+        assert_eq!(pils.process_str("+ 1 1"), "2");
+
+        let error = pils
+            .process_with_timeout(&program, Duration::from_millis(50))
+            .unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<Interrupt>(),
+            Some(&Interrupt::TimedOut {
+                timeout: Duration::from_millis(50)
+            })
+        );
+        assert_eq!(error.to_string(), "Timed out after 50ms");
+        assert_eq!(pils.process_str("nap 0"), "1");
+    }
+
+    #[test]
+    fn cancels_clones_separately() {
+        let pils = Interpreter::default();
+        let mut clone = pils.clone();
+        pils.cancellation_token().cancel();
+        assert_eq!(clone.process_str("+ 1 1"), "2");
+        clone.cancellation_token().cancel();
+        assert_eq!(clone.process_str("+ 1 1"), "Error: Evaluation cancelled");
+        assert_eq!(pils.clone().process_str("+ 1 1"), "2");
+    }
+
+    #[test]
+    fn keeps_configuration() {
+        let config = Config {
+            compile: true,
+            check_types: true,
+            ..Config::default()
+        };
+        let mut interpreter = Interpreter::new(config);
+        assert!(interpreter.config().compile);
+        assert_eq!(
+            interpreter.process_str("+ 1 {2}"),
+            "Error: Type error: '( + 1 { 2 } )' expected Num, found Qexpr"
+        );
+    }
+}
@@ -0,0 +1,62 @@
+//! Errors that stop an evaluation from the outside, rather than because the program is wrong.
+//! They can be told apart from other errors with `error.downcast_ref::<Interrupt>()`.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// Why an evaluation was stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Interrupt {
+    /// The program took more evaluation steps than the configured fuel.
+    OutOfFuel { steps: u64 },
+    /// Evaluations nested deeper than the configured maximum depth.
+    TooDeep { depth: usize },
+    /// A value would have grown the memory in use beyond the configured limit.
+    OutOfMemory { limit: usize },
+    /// The evaluation was cancelled through a [`CancellationToken`].
+    Cancelled,
+    /// The evaluation took longer than the timeout passed to
+    /// [`crate::Interpreter::process_with_timeout`].
+    TimedOut { timeout: Duration },
+}
+
+impl std::fmt::Display for Interrupt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::OutOfFuel { steps } => write!(f, "Out of fuel after {steps} steps"),
+            Self::TooDeep { depth } => write!(f, "Evaluation nested deeper than {depth} levels"),
+            Self::OutOfMemory { limit } => {
+                write!(f, "Out of memory, the limit is {limit} bytes")
+            }
+            Self::Cancelled => write!(f, "Evaluation cancelled"),
+            Self::TimedOut { timeout } => write!(f, "Timed out after {timeout:?}"),
+        }
+    }
+}
+
+impl std::error::Error for Interrupt {}
+
+/// Stops evaluations from another thread. Clones share their state, and once cancelled,
+/// evaluations fail with [`Interrupt::Cancelled`] until the token is reset.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,175 @@
+//! Conversion between values and JSON.
+//!
+//! Numbers, strings, q-expressions and maps correspond to JSON numbers, strings, arrays and objects.
+//! The empty s-expression `()` is `null`, and `true` and `false` are 1 and 0, like the results of `has?`.
+//! Symbols become strings and sets arrays, so they read back as strings and q-expressions.
+//! Object keys read back as numbers if they are written like numbers display, as symbols if they
+//! are written like symbols, and as strings otherwise, like `"a b"` or `"007"`.
+//! Objects repeating a key are refused.
+
+use crate::{
+    map::{Key, Map},
+    qexpr::Qexpr,
+    sexpr::Sexpr,
+    value::Value,
+};
+use im::{OrdMap, Vector};
+use serde::{
+    de::{self, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer,
+};
+use std::fmt;
+
+impl Value {
+    pub fn to_json(&self) -> Result<serde_json::Value, anyhow::Error> {
+        Ok(match self {
+            Self::Num(n) => serde_json::Value::from(*n),
+            Self::Sym(s) => serde_json::Value::from(s.as_str()),
+            Self::Str(s) => serde_json::Value::from(s.as_str()),
+            Self::Sexpr(Sexpr(s)) if s.is_empty() => serde_json::Value::Null,
+            Self::Qexpr(Qexpr(q)) => array(q)?,
+            Self::Set(s) => array(s.iter())?,
+            Self::Map(Map(m)) => serde_json::Value::Object(
+                m.iter()
+                    .map(|(k, v)| Ok((k.text(), v.to_json()?)))
+                    .collect::<Result<_, anyhow::Error>>()?,
+            ),
+            v @ (Self::Sexpr(_) | Self::Fun(_)) => {
+                return Err(anyhow::anyhow!("'{v}' cannot be converted to JSON"))
+            }
+        })
+    }
+
+    pub fn from_json(json: serde_json::Value) -> Result<Self, anyhow::Error> {
+        Ok(Json::deserialize(json)?.0)
+    }
+
+    /// Reads JSON text. Unlike [`Self::from_json`], this sees objects repeating a key,
+    /// which are refused like repeated keys in map literals.
+    pub fn from_json_str(text: &str) -> Result<Self, anyhow::Error> {
+        Ok(serde_json::from_str::<Json>(text)?.0)
+    }
+}
+
+/// A value read from JSON, as opposed to a value deserialized from an image.
+struct Json(Value);
+
+impl<'de> Deserialize<'de> for Json {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct JsonVisitor;
+
+        impl<'de> Visitor<'de> for JsonVisitor {
+            type Value = Json;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("JSON")
+            }
+
+            fn visit_unit<E: de::Error>(self) -> Result<Json, E> {
+                Ok(Json(Value::Sexpr(Sexpr::from_iter([]))))
+            }
+
+            fn visit_bool<E: de::Error>(self, b: bool) -> Result<Json, E> {
+                Ok(Json(Value::Num(i64::from(b))))
+            }
+
+            fn visit_i64<E: de::Error>(self, n: i64) -> Result<Json, E> {
+                Ok(Json(Value::Num(n)))
+            }
+
+            fn visit_u64<E: de::Error>(self, n: u64) -> Result<Json, E> {
+                i64::try_from(n)
+                    .map(|n| Json(Value::Num(n)))
+                    .map_err(|_| not_whole(n))
+            }
+
+            fn visit_f64<E: de::Error>(self, n: f64) -> Result<Json, E> {
+                Err(not_whole(n))
+            }
+
+            fn visit_str<E: de::Error>(self, s: &str) -> Result<Json, E> {
+                Ok(Json(Value::Str(s.to_string())))
+            }
+
+            fn visit_string<E: de::Error>(self, s: String) -> Result<Json, E> {
+                Ok(Json(Value::Str(s)))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Json, A::Error> {
+                let mut values = Vector::new();
+                while let Some(Json(value)) = seq.next_element()? {
+                    values.push_back(value);
+                }
+                Ok(Json(Value::Qexpr(Qexpr(values))))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut entries: A) -> Result<Json, A::Error> {
+                let mut map = OrdMap::new();
+                while let Some(text) = entries.next_key::<String>()? {
+                    let key = Key::from_text(&text);
+                    if map.contains_key(&key) {
+                        return Err(de::Error::custom(format!("duplicate map key '{key}'")));
+                    }
+                    let Json(value) = entries.next_value()?;
+                    map.insert(key, value);
+                }
+                Ok(Json(Value::Map(Map(map))))
+            }
+        }
+
+        deserializer.deserialize_any(JsonVisitor)
+    }
+}
+
+fn not_whole<E: de::Error>(n: impl fmt::Display) -> E {
+    E::custom(format!("{n} is not a whole number that fits into i64"))
+}
+
+fn array<'a>(
+    values: impl IntoIterator<Item = &'a Value>,
+) -> Result<serde_json::Value, anyhow::Error> {
+    Ok(serde_json::Value::Array(
+        values
+            .into_iter()
+            .map(Value::to_json)
+            .collect::<Result<_, _>>()?,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn round_trips() {
+        let json = json!({"name": "pils", "tags": ["lisp", 1, [], null], "2": {"nested": -3}});
+        let value = Value::from_json(json.clone()).unwrap();
+        assert_eq!(
+            value.to_string(),
+            r#"#{ 2 #{ nested -3 } name "pils" tags { "lisp" 1 {} () } }"#
+        );
+        assert_eq!(value.to_json().unwrap(), json);
+    }
+
+    #[test]
+    fn converts_other_values() {
+        assert_eq!(Value::from_json(json!(true)).unwrap(), Value::Num(1));
+        let value = Value::qexpr([Value::sym("a"), Value::set([Value::num(2), Value::num(1)])]);
+        assert_eq!(value.to_json().unwrap(), json!(["a", [1, 2]]));
+        assert!(Value::from_json(json!(1.5)).is_err());
+        assert_eq!(
+            Value::from_json_str(r#"{"a": 1, "b": {"a": 2}, "a": 3}"#)
+                .unwrap_err()
+                .to_string(),
+            "duplicate map key 'a' at line 1 column 27"
+        );
+        assert_eq!(
+            Value::from_json(json!({"7": 1, "007": 2}))
+                .unwrap()
+                .to_string(),
+            r#"#{ 7 1 "007" 2 }"#
+        );
+        assert!("( + 1 2 )".parse::<Value>().unwrap().to_json().is_err());
+    }
+}
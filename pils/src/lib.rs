@@ -1,31 +1,68 @@
-#![feature(lazy_cell)]
+// Lets the code generated by pils-derive refer to `::pils` inside this crate, too.
+extern crate self as pils;
 
-use crate::value::Value;
 use anyhow::{anyhow, Context, Error};
+use config::Config;
 use environment::Environment;
+pub use format::{from_str, to_string};
+pub use interpreter::Interpreter;
+pub use interrupt::{CancellationToken, Interrupt};
+pub use map::{Key, Map};
 use parser::{Pils, Rule};
 use pest::Parser;
+pub use qexpr::Qexpr;
+pub use set::Set;
+pub use sexpr::Sexpr;
 use std::{
     collections::HashMap,
+    path::Path,
     sync::{LazyLock, Mutex},
 };
+pub use symbol::Symbol;
+pub use value::{Value, Values};
 
 pub mod builtin;
+pub mod config;
+pub mod convert;
 pub mod environment;
-mod function;
+mod fold;
+pub mod format;
+pub mod function;
 pub mod help;
+mod image;
+pub mod interpreter;
+pub mod interrupt;
+mod json;
+pub mod lint;
+mod map;
+mod module;
 mod parser;
+mod pattern;
 mod qexpr;
+mod set;
 mod sexpr;
+mod symbol;
+pub mod types;
 mod value;
+mod vm;
 
 #[cfg(test)]
 mod test;
 
-static ENVIRONMENT: LazyLock<Mutex<Environment>> =
-    LazyLock::new(|| Mutex::new(Environment::default()));
+static INTERPRETER: LazyLock<Mutex<Interpreter>> =
+    LazyLock::new(|| Mutex::new(Interpreter::default()));
 
 pub fn process(input: &str) -> Result<Value, Error> {
+    INTERPRETER.lock().unwrap().process(input)
+}
+
+/// Evaluates a file, one program per line, so that it may import modules next to it.
+/// Returns the value of the last program.
+pub fn process_file(path: &Path) -> Result<Value, Error> {
+    INTERPRETER.lock().unwrap().process_file(path)
+}
+
+pub(crate) fn parse(input: &str) -> Result<Value, Error> {
     let mut pairs =
         Pils::parse(Rule::Pils, input).with_context(|| format!("Failed to parse input {input}"))?;
     let pair = pairs.next().ok_or_else(|| anyhow!("Empty pair"))?;
@@ -34,53 +71,41 @@ pub fn process(input: &str) -> Result<Value, Error> {
         return Err(anyhow::anyhow!("More than one element in pair"));
     }
 
-    let val = Value::from_pair(pair)
-        .map_err(|_| anyhow::anyhow!("Failed to create value"))? // accenture disapproves
-        .unwrap();
-
-    let mut env = ENVIRONMENT.lock().unwrap();
+    let val = Value::from_pair(pair)?.unwrap();
+    Ok(val)
+}
 
-    Value::eval(val, &mut env)
+pub fn configure(config: Config) {
+    INTERPRETER.lock().unwrap().configure(config);
 }
 
 #[must_use]
 pub fn process_str(line: &str) -> String {
-    let result = process(line.trim());
-    match result {
-        Ok(v) => format!("{v}"),
-        Err(e) => format!("Error: {e}"),
-    }
+    INTERPRETER.lock().unwrap().process_str(line)
+}
+
+pub fn save_image_str() -> Result<String, Error> {
+    INTERPRETER.lock().unwrap().save_image_str()
+}
+
+pub fn load_image_str(image: &str) -> Result<(), Error> {
+    INTERPRETER.lock().unwrap().load_image_str(image)
 }
 
 #[must_use]
 pub fn get_env_json() -> String {
-    let Ok(env) = ENVIRONMENT.lock() else {
-        return "Failed to acquire environment".to_string()
+    let Ok(interpreter) = INTERPRETER.lock() else {
+        return "Failed to acquire environment".to_string();
     };
-    let env = &env.0;
-    let env: HashMap<&String, &Value> = env
-        .iter()
-        .filter(|(_k, v)| !matches!(v, Value::Fun(_f)))
-        .collect();
-    serde_json::to_string_pretty(&env)
-        .context("Serialization fault")
-        .unwrap()
+    interpreter.get_env_json()
 }
 
 #[must_use]
 pub fn get_env_tuples() -> String {
-    let Ok(env) = ENVIRONMENT.lock() else {
-        return "Failed to acquire environment".to_string()
+    let Ok(interpreter) = INTERPRETER.lock() else {
+        return "Failed to acquire environment".to_string();
     };
-    let env = &env.0;
-    let env: HashMap<&String, String> = env
-        .iter()
-        .filter(|(_k, v)| !matches!(v, Value::Fun(_f)))
-        .map(|(k, v)| (k, format!("{v}")))
-        .collect();
-    serde_json::to_string_pretty(&env)
-        .context("Serialization fault")
-        .unwrap()
+    interpreter.get_env_tuples()
 }
 
 #[must_use]
@@ -96,11 +121,11 @@ pub fn get_example_environment() -> String {
 
     let val = Value::from_pair(pair).unwrap().unwrap();
     let env = Environment::from_iter([
-        ("key1".to_string(), val),
-        ("key2".to_string(), Value::Sym("function1".to_string())),
+        (Symbol::new("key1"), val),
+        (Symbol::new("key2"), Value::Sym(Symbol::new("function1"))),
     ]);
     let env: HashMap<String, String> = env
-        .0
+        .bindings
         .iter()
         .map(|(k, v)| (k.to_string(), format!("{v}")))
         .collect();
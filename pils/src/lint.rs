@@ -0,0 +1,480 @@
+//! Static checks that find mistakes in a program without evaluating it.
+
+pub use crate::function::Arity;
+use crate::{
+    environment::Environment,
+    parser::{Pils, Rule},
+    value::Value,
+};
+use anyhow::Context;
+use pest::{iterators::Pair, Parser};
+use std::collections::{HashMap, HashSet};
+
+/// A byte range in the linted source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Kind {
+    /// A symbol is evaluated, but nothing defines it.
+    Unbound(String),
+    /// Something that cannot be a function is called.
+    NotAFunction(String),
+    /// A builtin is called with the wrong number of arguments.
+    Arity {
+        name: String,
+        expected: Arity,
+        found: usize,
+    },
+    /// `def` is given a different number of values than names.
+    DefCount { names: usize, values: usize },
+    /// `def` redefines a builtin.
+    ShadowsBuiltin(String),
+}
+
+impl std::fmt::Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unbound(sym) => write!(f, "unbound symbol '{sym}'"),
+            Self::NotAFunction(v) => write!(f, "'{v}' is not a function"),
+            Self::Arity {
+                name,
+                expected,
+                found,
+            } => write!(f, "'{name}' takes {expected}, but got {found}"),
+            Self::DefCount { names, values } => {
+                write!(f, "'def' binds {names} names to {values} values")
+            }
+            Self::ShadowsBuiltin(name) => write!(f, "'def' shadows builtin '{name}'"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    pub span: Span,
+    pub kind: Kind,
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.span, self.kind)
+    }
+}
+
+/// A parsed expression along with where it came from.
+#[derive(Debug, Clone)]
+struct Node {
+    span: Span,
+    text: String,
+    expr: Expr,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Num,
+    Sym(String),
+    Str,
+    Sexpr(Vec<Node>),
+    Qexpr(Vec<Node>),
+    Map,
+}
+
+impl Node {
+    fn from_pair(pair: Pair<Rule>) -> Option<Self> {
+        let span = Span {
+            start: pair.as_span().start(),
+            end: pair.as_span().end(),
+        };
+        let text = pair.as_str().to_string();
+        let children = |pair: Pair<Rule>| pair.into_inner().filter_map(Self::from_pair).collect();
+        let expr = match pair.as_rule() {
+            Rule::WHITESPACE => return None,
+            Rule::Expr => return pair.into_inner().find_map(Self::from_pair),
+            Rule::Number => Expr::Num,
+            Rule::Symbol => Expr::Sym(pair.as_str().to_string()),
+            Rule::String => Expr::Str,
+            Rule::Sexpr | Rule::Pils => Expr::Sexpr(children(pair)),
+            Rule::Qexpr => Expr::Qexpr(children(pair)),
+            Rule::Map => Expr::Map,
+        };
+        Some(Self { span, text, expr })
+    }
+
+    /// Whether this node certainly evaluates to something other than a function.
+    fn is_data(&self) -> bool {
+        matches!(
+            self.expr,
+            Expr::Num | Expr::Str | Expr::Qexpr(_) | Expr::Map
+        )
+    }
+}
+
+/// What the linter knows about a defined name.
+#[derive(Debug, Clone)]
+enum Binding {
+    /// A builtin, with its arity if it declares one.
+    Builtin(Option<Arity>),
+    /// A user definition, with its value if that was a literal.
+    Defined(Option<Node>),
+}
+
+/// Checks programs, remembering the definitions made by earlier ones.
+#[derive(Debug, Clone)]
+pub struct Linter {
+    bindings: HashMap<String, Binding>,
+    /// The prefixes given to `import`, under which any name may be defined.
+    prefixes: HashSet<String>,
+    /// Whether something was imported under the name of its module, which is only known
+    /// once the module is loaded, so that any prefixed name may be defined.
+    imports_by_module_name: bool,
+    warnings: Vec<Warning>,
+}
+
+impl Default for Linter {
+    fn default() -> Self {
+        Self::of(&Environment::default())
+    }
+}
+
+/// Checks a single program against the default environment.
+pub fn lint(input: &str) -> Result<Vec<Warning>, anyhow::Error> {
+    Linter::default().lint(input)
+}
+
+impl Linter {
+    /// A linter knowing the builtins and definitions of `env`, including registered builtins,
+    /// whose arities come from [`crate::function::Function::arity`].
+    pub fn of(env: &Environment) -> Self {
+        let bindings = env
+            .bindings
+            .iter()
+            .map(|(sym, value)| {
+                let binding = match value {
+                    Value::Fun(fun) if env.is_builtin(*sym, value) => Binding::Builtin(fun.arity()),
+                    _ => Binding::Defined(None),
+                };
+                (sym.as_str().to_string(), binding)
+            })
+            .collect();
+        Self {
+            bindings,
+            prefixes: HashSet::new(),
+            imports_by_module_name: false,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Tells the linter about a name defined elsewhere, e.g. by an earlier REPL line.
+    pub fn define(&mut self, name: &str) {
+        self.bindings
+            .insert(name.to_string(), Binding::Defined(None));
+    }
+
+    pub fn lint(&mut self, input: &str) -> Result<Vec<Warning>, anyhow::Error> {
+        let pair = Pils::parse(Rule::Pils, input)
+            .with_context(|| format!("Failed to parse input {input}"))?
+            .next()
+            .context("Empty pair")?;
+        if let Some(program) = Node::from_pair(pair) {
+            self.eval(&program);
+        }
+        Ok(std::mem::take(&mut self.warnings))
+    }
+
+    fn warn(&mut self, span: Span, kind: Kind) {
+        self.warnings.push(Warning { span, kind });
+    }
+
+    /// Walks a node in the order the evaluator would evaluate it.
+    fn eval(&mut self, node: &Node) {
+        match &node.expr {
+            Expr::Sym(sym) if !self.is_defined(sym) => {
+                self.warn(node.span, Kind::Unbound(sym.clone()));
+            }
+            Expr::Sexpr(items) => {
+                for item in items {
+                    self.eval(item);
+                }
+                if let [head, args @ ..] = &items[..] {
+                    if !args.is_empty() {
+                        self.call(head, args);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn call(&mut self, head: &Node, args: &[Node]) {
+        if head.is_data() {
+            self.warn(head.span, Kind::NotAFunction(head.text.clone()));
+            return;
+        }
+        let Expr::Sym(name) = &head.expr else {
+            return;
+        };
+        match self.bindings.get(name) {
+            Some(Binding::Defined(Some(value))) if value.is_data() => {
+                self.warn(head.span, Kind::NotAFunction(name.clone()));
+            }
+            Some(Binding::Builtin(Some(arity))) if !arity.accepts(args.len()) => {
+                let kind = Kind::Arity {
+                    name: name.clone(),
+                    expected: *arity,
+                    found: args.len(),
+                };
+                self.warn(head.span, kind);
+            }
+            Some(Binding::Builtin(_)) => match name.as_str() {
+                "def" | "redef" => self.def(args),
+                "import" => self.import(args),
+                "eval" => {
+                    if let Expr::Qexpr(items) = &args[0].expr {
+                        let code = Node {
+                            expr: Expr::Sexpr(items.clone()),
+                            ..args[0].clone()
+                        };
+                        self.eval(&code);
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    /// Whether `sym` is bound, or may be bound by an `import` like `math/square`.
+    fn is_defined(&self, sym: &str) -> bool {
+        if self.bindings.contains_key(sym) {
+            return true;
+        }
+        match sym.split_once('/') {
+            Some((prefix, name)) if !prefix.is_empty() && !name.is_empty() => {
+                self.imports_by_module_name || self.prefixes.contains(prefix)
+            }
+            _ => false,
+        }
+    }
+
+    fn import(&mut self, args: &[Node]) {
+        match args.get(1).map(|prefix| &prefix.expr) {
+            None => self.imports_by_module_name = true,
+            Some(Expr::Qexpr(prefix)) => {
+                if let [Node {
+                    expr: Expr::Sym(prefix),
+                    ..
+                }] = &prefix[..]
+                {
+                    self.prefixes.insert(prefix.clone());
+                }
+            }
+            Some(_) => {}
+        }
+    }
+
+    fn def(&mut self, args: &[Node]) {
+        let (names, values) = args.split_first().unwrap();
+        let names = match &names.expr {
+            Expr::Qexpr(names) => names.clone(),
+            // A symbol whose definition was a literal q-expression, like `def {args} {a b}`.
+            Expr::Sym(sym) => match self.bindings.get(sym) {
+                Some(Binding::Defined(Some(Node {
+                    expr: Expr::Qexpr(names),
+                    ..
+                }))) => names.clone(),
+                _ => return,
+            },
+            _ => return,
+        };
+        if names.len() != values.len() {
+            let span = Span {
+                start: args[0].span.start,
+                end: args[args.len() - 1].span.end,
+            };
+            let kind = Kind::DefCount {
+                names: names.len(),
+                values: values.len(),
+            };
+            self.warn(span, kind);
+        }
+        for (i, name) in names.iter().enumerate() {
+            let value = values.get(i).filter(|v| v.is_data()).cloned();
+            self.define_name(name, value);
+        }
+    }
+
+    /// Defines the symbols of a name passed to `def`, which may destructure like `{b c}`.
+    fn define_name(&mut self, name: &Node, value: Option<Node>) {
+        match &name.expr {
+            Expr::Sym(sym) if sym == "_" || sym == "&" => {}
+            Expr::Sym(sym) => {
+                // Drop type annotations like the ':Num' in 'x:Num'.
+                let sym = &sym.split(':').next().unwrap_or_default().to_string();
+                if let Some(Binding::Builtin(_)) = self.bindings.get(sym) {
+                    self.warn(name.span, Kind::ShadowsBuiltin(sym.clone()));
+                }
+                self.bindings.insert(sym.clone(), Binding::Defined(value));
+            }
+            Expr::Qexpr(names) => {
+                for name in names {
+                    self.define_name(name, None);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::function::Function;
+
+    fn kinds(input: &str) -> Vec<Kind> {
+        lint(input).unwrap().into_iter().map(|w| w.kind).collect()
+    }
+
+    #[test]
+    fn accepts_correct_programs() {
+        assert!(kinds("* 2 (+ 4 5) (/ 10 2) (-2) (- 1 2 3)").is_empty());
+        assert!(kinds("eval { tail ( list 1 2 3 4 ) }").is_empty());
+        assert!(kinds("(eval (head {+ - + - * /})) 10 20").is_empty());
+    }
+
+    #[test]
+    fn reports_unbound_symbols_with_spans() {
+        let warnings = lint("+ 1 hello").unwrap();
+        assert_eq!(
+            warnings,
+            [Warning {
+                span: Span { start: 4, end: 9 },
+                kind: Kind::Unbound("hello".to_string()),
+            }]
+        );
+        assert_eq!(warnings[0].to_string(), "4..9: unbound symbol 'hello'");
+    }
+
+    #[test]
+    fn ignores_quoted_symbols() {
+        assert!(kinds("list {hello}").is_empty());
+    }
+
+    #[test]
+    fn looks_into_eval_of_literals() {
+        assert_eq!(
+            kinds("eval {+ 1 hello}"),
+            [Kind::Unbound("hello".to_string())]
+        );
+    }
+
+    #[test]
+    fn tracks_definitions() {
+        let mut linter = Linter::default();
+        assert!(linter.lint("def {x} 100").unwrap().is_empty());
+        assert!(linter.lint("+ x 1").unwrap().is_empty());
+        assert!(linter.lint("def {arglist} {a b}").unwrap().is_empty());
+        assert!(linter.lint("def arglist 1 2").unwrap().is_empty());
+        assert!(linter.lint("+ a b").unwrap().is_empty());
+        linter.define("outside");
+        assert!(linter.lint("+ outside 1").unwrap().is_empty());
+        assert!(linter.lint("def {n:Num} 1").unwrap().is_empty());
+        assert!(linter.lint("+ n 1").unwrap().is_empty());
+        assert!(linter.lint("def {p {q & r}} 1 {2 3}").unwrap().is_empty());
+        assert!(linter.lint("list p q r").unwrap().is_empty());
+    }
+
+    #[test]
+    fn treats_imported_names_as_defined() {
+        let mut linter = Linter::default();
+        assert_eq!(
+            linter.lint("+ m/pi 1").unwrap()[0].kind,
+            Kind::Unbound("m/pi".to_string())
+        );
+        assert!(linter.lint("import {lib/math} {m}").unwrap().is_empty());
+        assert!(linter.lint("+ m/pi 1").unwrap().is_empty());
+        assert_eq!(
+            linter.lint("+ math/pi 1").unwrap()[0].kind,
+            Kind::Unbound("math/pi".to_string())
+        );
+        assert!(linter.lint("import {lib/math}").unwrap().is_empty());
+        assert!(linter.lint("+ math/pi 1").unwrap().is_empty());
+        assert_eq!(kinds("/ 4 2"), []);
+    }
+
+    #[test]
+    fn takes_arities_from_the_environment() {
+        let mut env = Environment::default();
+        env.add_function(
+            Function::typed("twice", |(n,): (i64,)| Ok(2 * n)).with_arity(Arity::Exactly(1)),
+        );
+        env.add_function(Function::new("any", |args, _env| Ok(args)));
+        let mut linter = Linter::of(&env);
+        assert_eq!(
+            linter.lint("twice 1 2").unwrap()[0].kind,
+            Kind::Arity {
+                name: "twice".to_string(),
+                expected: Arity::Exactly(1),
+                found: 2,
+            }
+        );
+        assert!(linter.lint("any 1 2 3").unwrap().is_empty());
+    }
+
+    #[test]
+    fn reports_calls_of_non_functions() {
+        assert_eq!(kinds("(1 2 3)"), [Kind::NotAFunction("1".to_string())]);
+        assert_eq!(kinds("{+ 1} 2"), [Kind::NotAFunction("{+ 1}".to_string())]);
+        let mut linter = Linter::default();
+        linter.lint("def {x} 5").unwrap();
+        assert_eq!(
+            linter
+                .lint("x 1")
+                .unwrap()
+                .into_iter()
+                .map(|w| w.kind)
+                .collect::<Vec<_>>(),
+            [Kind::NotAFunction("x".to_string())]
+        );
+    }
+
+    #[test]
+    fn reports_wrong_arity() {
+        assert_eq!(
+            kinds("head {1} {2}"),
+            [Kind::Arity {
+                name: "head".to_string(),
+                expected: Arity::Exactly(1),
+                found: 2,
+            }]
+        );
+        assert_eq!(
+            kinds("def {a b c} 1"),
+            [Kind::DefCount {
+                names: 3,
+                values: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn reports_shadowed_builtins() {
+        let warnings = lint("def {x +} 1 2").unwrap();
+        assert_eq!(
+            warnings,
+            [Warning {
+                span: Span { start: 7, end: 8 },
+                kind: Kind::ShadowsBuiltin("+".to_string()),
+            }]
+        );
+    }
+}
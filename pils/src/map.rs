@@ -0,0 +1,317 @@
+use crate::{qexpr::Qexpr, symbol::Symbol, value::Value};
+use anyhow::Context;
+use im::OrdMap;
+use itertools::Itertools;
+use serde::{de, Deserializer, Serializer};
+use serde_derive::{Deserialize, Serialize};
+
+/// A map key. Only atoms can be used as keys, which keeps maps
+/// representable as JSON objects.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Key {
+    Num(i64),
+    Sym(Symbol),
+    Str(String),
+}
+
+impl Key {
+    /// The key written as `text`, like a JSON object key: a number if it is one written the way
+    /// numbers display, otherwise a symbol if it reads back as one, and a string if not.
+    /// Text like `007` stays a string, so that different keys stay different.
+    pub(crate) fn from_text(text: &str) -> Self {
+        text.parse()
+            .ok()
+            .filter(|n: &i64| n.to_string() == text)
+            .map_or_else(|| Self::from_str_key(text), Self::Num)
+    }
+
+    /// A string used as key, like that of a Rust map: a symbol if it reads back as one,
+    /// so that `#{ a 1 }` stays readable, and a string otherwise.
+    pub(crate) fn from_str_key(text: &str) -> Self {
+        if Symbol::is_readable(text) {
+            Self::Sym(Symbol::new(text))
+        } else {
+            Self::Str(text.to_string())
+        }
+    }
+
+    /// The text of the key, as used for JSON object keys.
+    pub(crate) fn text(&self) -> String {
+        match self {
+            Self::Num(n) => n.to_string(),
+            Self::Sym(s) => s.as_str().to_string(),
+            Self::Str(s) => s.clone(),
+        }
+    }
+}
+
+impl TryFrom<Value> for Key {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Num(n) => Ok(Self::Num(n)),
+            Value::Sym(s) => Ok(Self::Sym(s)),
+            Value::Str(s) => Ok(Self::Str(s)),
+            v => Err(anyhow::anyhow!("'{v}' cannot be used as a map key")),
+        }
+    }
+}
+
+impl From<Key> for Value {
+    fn from(key: Key) -> Self {
+        match key {
+            Key::Num(n) => Self::Num(n),
+            Key::Sym(s) => Self::Sym(s),
+            Key::Str(s) => Self::Str(s),
+        }
+    }
+}
+
+impl std::fmt::Display for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Value::from(self.clone()))
+    }
+}
+
+/// String keys are serialized quoted, like `"\"a b\""`, so that they read back as strings.
+impl serde::Serialize for Key {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Num(n) => serializer.serialize_i64(*n),
+            Self::Sym(s) => serializer.serialize_str(s.as_str()),
+            Self::Str(_) => serializer.serialize_str(&self.to_string()),
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Key {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct KeyVisitor;
+
+        impl<'de> de::Visitor<'de> for KeyVisitor {
+            type Value = Key;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "a number, a symbol or a string")
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Key, E> {
+                Ok(Key::Num(v))
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Key, E> {
+                i64::try_from(v).map(Key::Num).map_err(E::custom)
+            }
+
+            // JSON object keys are always strings, so numeric keys come back as text.
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Key, E> {
+                if v.starts_with('"') {
+                    return match v.parse() {
+                        Ok(Value::Str(s)) => Ok(Key::Str(s)),
+                        _ => Err(E::custom(format!("malformed string key {v}"))),
+                    };
+                }
+                Ok(Key::from_text(v))
+            }
+        }
+
+        deserializer.deserialize_any(KeyVisitor)
+    }
+}
+
+/// An associative collection, ordered by key so that it always displays the same way.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Map(pub(crate) OrdMap<Key, Value>);
+
+impl Map {
+    /// Builds a map from a flat sequence of alternating keys and values, as in `#{ a 1 b 2 }`.
+    pub fn from_pairs(items: impl IntoIterator<Item = Value>) -> Result<Self, anyhow::Error> {
+        let mut map = OrdMap::new();
+        for mut chunk in &items.into_iter().chunks(2) {
+            let key = chunk.next().context("empty map entry")?;
+            let value = chunk
+                .next()
+                .with_context(|| format!("map key '{key}' has no value"))?;
+            let key = Key::try_from(key)?;
+            if map.contains_key(&key) {
+                return Err(anyhow::anyhow!("duplicate map key '{key}'"));
+            }
+            map.insert(key, value);
+        }
+        Ok(Self(map))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn lookup(&self, key: &Key) -> Option<&Value> {
+        self.0.get(key)
+    }
+
+    /// The entries, ordered by key.
+    pub fn iter(&self) -> impl Iterator<Item = (&Key, &Value)> {
+        self.0.iter()
+    }
+
+    pub fn get(&self, key: &Key) -> Result<Value, anyhow::Error> {
+        self.0
+            .get(key)
+            .cloned()
+            .with_context(|| format!("key '{key}' not found in map"))
+    }
+
+    pub fn assoc(mut self, entries: impl IntoIterator<Item = (Key, Value)>) -> Value {
+        self.0.extend(entries);
+        Value::Map(self)
+    }
+
+    pub fn dissoc<'a>(mut self, keys: impl IntoIterator<Item = &'a Key>) -> Value {
+        for key in keys {
+            self.0.remove(key);
+        }
+        Value::Map(self)
+    }
+
+    pub fn keys(self) -> Value {
+        Value::Qexpr(self.0.keys().cloned().map(Value::from).collect::<Qexpr>())
+    }
+
+    pub fn vals(self) -> Value {
+        Value::Qexpr(self.0.values().cloned().collect::<Qexpr>())
+    }
+
+    pub fn has(&self, key: &Key) -> Value {
+        Value::Num(i64::from(self.0.contains_key(key)))
+    }
+}
+
+impl std::fmt::Display for Map {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "#{{}}");
+        }
+        write!(f, "#{{")?;
+        write!(
+            f,
+            " {} ",
+            self.0.iter().map(|(k, v)| format!("{k} {v}")).join(" ")
+        )?;
+        write!(f, "}}")?;
+        Ok(())
+    }
+}
+
+impl FromIterator<(Key, Value)> for Map {
+    fn from_iter<T: IntoIterator<Item = (Key, Value)>>(iter: T) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sym(s: &str) -> Key {
+        Key::Sym(Symbol::new(s))
+    }
+
+    #[test]
+    fn from_pairs() {
+        let map = Map::from_pairs([
+            Value::Sym(Symbol::new("b")),
+            Value::Num(2),
+            Value::Sym(Symbol::new("a")),
+            Value::Num(1),
+        ])
+        .unwrap();
+        assert_eq!(
+            map,
+            Map::from_iter([(sym("a"), Value::Num(1)), (sym("b"), Value::Num(2))])
+        );
+    }
+
+    #[test]
+    fn rejects_odd_number_of_items() {
+        let err = Map::from_pairs([Value::Sym(Symbol::new("a"))]).unwrap_err();
+        assert_eq!(format!("{err}"), "map key 'a' has no value");
+    }
+
+    #[test]
+    fn rejects_duplicate_keys() {
+        let err = Map::from_pairs([
+            Value::Sym(Symbol::new("a")),
+            Value::Num(1),
+            Value::Sym(Symbol::new("a")),
+            Value::Num(2),
+        ])
+        .unwrap_err();
+        assert_eq!(format!("{err}"), "duplicate map key 'a'");
+    }
+
+    #[test]
+    fn rejects_compound_keys() {
+        let err = Map::from_pairs([Value::Qexpr(Qexpr::from_iter([])), Value::Num(1)]);
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn assoc_and_dissoc() {
+        let map = Map::from_iter([(sym("a"), Value::Num(1))]);
+        let Value::Map(map) = map.assoc([(Key::Num(7), Value::Num(2))]) else {
+            panic!("assoc must return a map");
+        };
+        assert_eq!(map.get(&Key::Num(7)).unwrap(), Value::Num(2));
+        let Value::Map(map) = map.dissoc(&[sym("a")]) else {
+            panic!("dissoc must return a map");
+        };
+        assert_eq!(map.has(&sym("a")), Value::Num(0));
+    }
+
+    #[test]
+    fn displays_in_key_order() {
+        let map = Map::from_iter([(sym("b"), Value::Num(2)), (Key::Num(1), Value::Num(1))]);
+        assert_eq!(format!("{map}"), "#{ 1 1 b 2 }");
+    }
+
+    #[test]
+    fn serializes_as_json_object() {
+        let map = Map::from_iter([(sym("a"), Value::Num(1)), (Key::Num(2), Value::Num(3))]);
+        let json = serde_json::to_string(&map).unwrap();
+        assert_eq!(json, r#"{"2":{"Num":3},"a":{"Num":1}}"#);
+        let back: Map = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, map);
+    }
+
+    #[test]
+    fn keeps_string_keys() {
+        let map = Map::from_iter([
+            (Key::Str("a b".to_string()), Value::Num(1)),
+            (Key::Str("42".to_string()), Value::Num(2)),
+            (Key::Str(String::new()), Value::Num(3)),
+            (sym("a"), Value::Num(4)),
+        ]);
+        assert_eq!(map.to_string(), r#"#{ a 4 "" 3 "42" 2 "a b" 1 }"#);
+        assert_eq!(
+            Value::Map(map.clone())
+                .to_string()
+                .parse::<Value>()
+                .unwrap(),
+            Value::Map(map.clone())
+        );
+        let back: Map = serde_json::from_str(&serde_json::to_string(&map).unwrap()).unwrap();
+        assert_eq!(back, map);
+        assert_eq!(Key::from_text("a b"), Key::Str("a b".to_string()));
+        assert_eq!(Key::from_text("4a"), Key::Str("4a".to_string()));
+        assert_eq!(Key::from_text("-"), sym("-"));
+        assert_eq!(Key::from_text("-12"), Key::Num(-12));
+        assert_eq!(Key::from_text("007"), Key::Str("007".to_string()));
+        assert_eq!(Key::from_text("-0"), Key::Str("-0".to_string()));
+        assert_eq!(Key::from_text("+5"), sym("+5"));
+    }
+}
@@ -0,0 +1,276 @@
+//! Modules are files of pils code, one program per line. A module names itself with
+//! `module {math}` and shares definitions with `export {square}`. `import {math}` loads
+//! `math.pils` next to the importing file and binds its exports as `math/square`.
+
+use crate::{environment::Environment, function::Registry, symbol::Symbol, value::Value};
+use anyhow::Context;
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+/// The extension of module files, which `import` leaves out.
+const EXTENSION: &str = "pils";
+
+/// A loaded module: its name and the values it exports.
+#[derive(Debug)]
+pub(crate) struct Module {
+    pub(crate) name: Symbol,
+    pub(crate) exports: Vec<(Symbol, Value)>,
+}
+
+/// The modules of an interpreter, so that each is evaluated only once.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Modules {
+    loaded: HashMap<PathBuf, Arc<Module>>,
+    /// Modules that are being loaded, to detect cyclic imports.
+    loading: Vec<PathBuf>,
+}
+
+/// Finds the file of a module like `lib/math`, relative to the file being evaluated,
+/// or to the working directory if there is none.
+/// Absolute paths and `..` are rejected, so imports stay below that directory.
+pub(crate) fn resolve(path: Symbol, env: &Environment) -> Result<PathBuf, anyhow::Error> {
+    let relative = Path::new(path.as_str())
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    if !relative {
+        return Err(anyhow::anyhow!(
+            "Module '{path}' must be a relative path without '..'"
+        ));
+    }
+    let base = env
+        .file
+        .as_deref()
+        .and_then(Path::parent)
+        .unwrap_or(Path::new(""));
+    let file = base.join(path.as_str()).with_extension(EXTENSION);
+    file.canonicalize()
+        .with_context(|| format!("Module '{path}' not found at {}", file.display()))
+}
+
+/// Loads the module in `file`, or returns it if it was loaded before.
+pub(crate) fn load(file: &Path, env: &mut Environment) -> Result<Arc<Module>, anyhow::Error> {
+    if let Some(module) = env.modules.loaded.get(file) {
+        return Ok(module.clone());
+    }
+    if env.modules.loading.iter().any(|f| f == file) {
+        return Err(anyhow::anyhow!("Cyclic import of {}", file.display()));
+    }
+    let source = std::fs::read_to_string(file)
+        .with_context(|| format!("Failed to read module {}", file.display()))?;
+
+    // Modules are evaluated in an environment of their own, sharing only the builtins,
+    // including those registered by an embedder, and the module cache.
+    let mut module_env = Environment::default();
+    for function in Registry::of(env).functions() {
+        if !module_env.is_constant(Symbol::new(function.name)) {
+            module_env.add_function(function.clone());
+        }
+    }
+    module_env.configure(env.config.clone());
+    module_env.cancellation = env.cancellation.clone();
+    module_env.deadline = env.deadline;
+    module_env.file = Some(file.to_path_buf());
+    module_env.modules = std::mem::take(&mut env.modules);
+    module_env.modules.loading.push(file.to_path_buf());
+    let result = crate::interpreter::evaluate_source(&source, &mut module_env)
+        .with_context(|| format!("in module {}", file.display()));
+    module_env.modules.loading.pop();
+    env.modules = std::mem::take(&mut module_env.modules);
+    result?;
+
+    let name = match module_env.module {
+        Some(name) => name,
+        None => Symbol::new(&file.file_stem().unwrap_or_default().to_string_lossy()),
+    };
+    let exports = module_env
+        .exports
+        .iter()
+        .map(|sym| match module_env.get(*sym) {
+            Some(value) => Ok((*sym, value.clone())),
+            None => Err(anyhow::anyhow!("Module '{name}' exports undefined '{sym}'")),
+        })
+        .collect::<Result<Vec<_>, anyhow::Error>>()?;
+    let module = Arc::new(Module { name, exports });
+    env.modules
+        .loaded
+        .insert(file.to_path_buf(), module.clone());
+    Ok(module)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{interpreter::evaluate_source, parse, Config};
+    use tempfile::TempDir;
+
+    /// Writes module files into a fresh directory, which is removed when dropped.
+    fn modules(name: &str, files: &[(&str, &str)]) -> TempDir {
+        let dir = tempfile::Builder::new()
+            .prefix(&format!("pils-{name}-"))
+            .tempdir()
+            .unwrap();
+        for (file, source) in files {
+            let path = dir.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, source).unwrap();
+        }
+        dir
+    }
+
+    /// An environment evaluating a file in `dir`.
+    fn env(dir: &Path) -> Environment {
+        let mut env = Environment::default();
+        env.file = Some(dir.join("main.pils"));
+        env
+    }
+
+    fn run(dir: &Path, source: &str) -> Result<String, String> {
+        let mut env = env(dir);
+        evaluate_source(source, &mut env)
+            .map(|v| v.to_string())
+            .map_err(|e| format!("{e:#}"))
+    }
+
+    #[test]
+    fn imports_exports_under_prefix() {
+        let dir = modules(
+            "prefix",
+            &[(
+                "lib/math.pils",
+                "module {math}\n\n; Constants\ndef {pi e} 3 2\nexport {pi}",
+            )],
+        );
+        assert_eq!(
+            run(dir.path(), "import {lib/math}\nmath/pi"),
+            Ok("3".to_string())
+        );
+        assert_eq!(
+            run(dir.path(), "import {lib/math}\ne"),
+            Err("line 2: unbound symbol".to_string())
+        );
+        assert_eq!(
+            run(dir.path(), "import {lib/math} {m}\n+ m/pi 1"),
+            Ok("4".to_string())
+        );
+    }
+
+    #[test]
+    fn resolves_relative_to_importing_file() {
+        let dir = modules(
+            "relative",
+            &[
+                ("a.pils", "import {lib/b}\ndef {x} b/y\nexport {x}"),
+                ("lib/b.pils", "import {c}\ndef {y} c/z\nexport {y}"),
+                ("lib/c.pils", "def {z} 7\nexport {z}"),
+                ("c.pils", "def {z} 0\nexport {z}"),
+            ],
+        );
+        assert_eq!(run(dir.path(), "import {a}\na/x"), Ok("7".to_string()));
+    }
+
+    #[test]
+    fn evaluates_modules_once() {
+        let dir = modules("once", &[("m.pils", "def {v} 1\nexport {v}")]);
+        let mut env = env(dir.path());
+        evaluate_source("import {m}", &mut env).unwrap();
+        std::fs::write(dir.path().join("m.pils"), "def {v} 2\nexport {v}").unwrap();
+        evaluate_source("import {m}", &mut env).unwrap();
+        let v = parse("m/v").unwrap().eval(&mut env).unwrap();
+        assert_eq!(v.to_string(), "1");
+    }
+
+    #[test]
+    fn reports_cycles_and_bad_exports() {
+        let dir = modules(
+            "cycles",
+            &[
+                ("a.pils", "import {b}"),
+                ("b.pils", "import {a}"),
+                ("c.pils", "export {nothing}"),
+            ],
+        );
+        assert!(run(dir.path(), "import {a}")
+            .unwrap_err()
+            .contains("b.pils: line 1: Cyclic import of"));
+        assert_eq!(
+            run(dir.path(), "import {c}"),
+            Err("line 1: Module 'c' exports undefined 'nothing'".to_string())
+        );
+        assert!(run(dir.path(), "import {missing}")
+            .unwrap_err()
+            .starts_with("line 1: Module 'missing' not found"));
+    }
+
+    #[test]
+    fn shares_registered_functions() {
+        let dir = modules(
+            "registered",
+            &[(
+                "twice.pils",
+                "def {four} (twice 2)
+export {four}",
+            )],
+        );
+        let mut env = env(dir.path());
+        env.add_function(crate::function::Function::typed("twice", |(n,): (i64,)| {
+            Ok(2 * n)
+        }));
+        assert_eq!(
+            evaluate_source(
+                "import {twice}
+twice/four",
+                &mut env
+            )
+            .unwrap(),
+            Value::Num(4)
+        );
+    }
+
+    #[test]
+    fn limits_memory_of_exports() {
+        let dir = modules(
+            "memory",
+            &[("big.pils", "def {xs} {1 2 3 4 5 6 7 8}\nexport {xs}")],
+        );
+        let mut env = env(dir.path());
+        env.configure(Config {
+            memory_limit: Some(1 << 30),
+            ..Config::default()
+        });
+        env.config.memory_limit = Some(env.memory + 12 * std::mem::size_of::<Value>());
+        evaluate_source("def {pad} {1 2 3}", &mut env).unwrap();
+        assert_eq!(
+            evaluate_source("import {big}", &mut env)
+                .map_err(|e| format!("{e:#}"))
+                .unwrap_err(),
+            format!(
+                "line 1: Out of memory, the limit is {} bytes",
+                env.config.memory_limit.unwrap()
+            )
+        );
+        assert!(env.get(Symbol::new("big/xs")).is_none());
+    }
+
+    #[test]
+    fn rejects_paths_outside_the_directory() {
+        let dir = modules("outside", &[("lib/a.pils", "def {x} 1\nexport {x}")]);
+        let env = env(&dir.path().join("lib"));
+        let absolute = dir.path().join("lib/a");
+        for path in ["../lib/a", "lib/../../a", absolute.to_str().unwrap()] {
+            assert_eq!(
+                resolve(Symbol::new(path), &env).unwrap_err().to_string(),
+                format!("Module '{path}' must be a relative path without '..'")
+            );
+        }
+        assert_eq!(
+            run(dir.path(), &format!("import {{{}}}", absolute.display())),
+            Err(format!(
+                "line 1: Module '{}' must be a relative path without '..'",
+                absolute.display()
+            ))
+        );
+    }
+}
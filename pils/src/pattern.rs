@@ -0,0 +1,182 @@
+//! Patterns, as used by `match`: numbers match themselves, `_` matches anything,
+//! other symbols match anything and bind it, and q-expressions like `{x & xs}`
+//! match q-expressions element by element, with `& rest` binding what is left.
+//! In `match`, symbols annotated like `x:Num` only match values of that type.
+
+use crate::{
+    qexpr::Qexpr,
+    symbol::Symbol,
+    types::{self, Type},
+    value::Value,
+};
+use std::collections::HashMap;
+
+/// Matches a value against a pattern of `match`, returning the variables it binds
+/// without their annotations, or `None` if it does not match.
+pub(crate) fn matches(
+    pattern: &Value,
+    value: &Value,
+) -> Result<Option<HashMap<Symbol, Value>>, anyhow::Error> {
+    let mut bindings = HashMap::new();
+    if !bind(pattern, value, &mut bindings)? {
+        return Ok(None);
+    }
+    let mut typed = HashMap::new();
+    for (sym, value) in bindings {
+        let (name, ty) = types::parse_param(sym)?;
+        if ty.is_some_and(|ty| !ty.accepts(Type::of(&value))) {
+            return Ok(None);
+        }
+        typed.insert(name, value);
+    }
+    Ok(Some(typed))
+}
+
+/// Matches a value against a pattern, adding the variables it binds to `bindings`,
+/// annotations included, which `def` checks rather than matches.
+/// Returns `Ok(false)` if the value does not match, and an error if the pattern is malformed.
+pub(crate) fn bind(
+    pattern: &Value,
+    value: &Value,
+    bindings: &mut HashMap<Symbol, Value>,
+) -> Result<bool, anyhow::Error> {
+    match pattern {
+        Value::Sym(sym) if sym.as_str() == "_" => Ok(true),
+        Value::Sym(sym) if sym.as_str() == "&" => {
+            Err(anyhow::anyhow!("'&' outside of a q-expression pattern"))
+        }
+        Value::Sym(sym) => {
+            bindings.insert(*sym, value.clone());
+            Ok(true)
+        }
+        Value::Qexpr(Qexpr(patterns)) => {
+            let Value::Qexpr(Qexpr(values)) = value else {
+                return Ok(false);
+            };
+            let (patterns, rest) = split_rest(patterns)?;
+            let enough = match rest {
+                Some(_) => values.len() >= patterns.len(),
+                None => values.len() == patterns.len(),
+            };
+            if !enough {
+                return Ok(false);
+            }
+            for (pattern, value) in patterns.iter().zip(values) {
+                if !bind(pattern, value, bindings)? {
+                    return Ok(false);
+                }
+            }
+            if let Some(rest) = rest {
+                let remaining = values.clone().slice(patterns.len()..);
+                return bind(rest, &Value::Qexpr(Qexpr(remaining)), bindings);
+            }
+            Ok(true)
+        }
+        literal => Ok(literal == value),
+    }
+}
+
+/// Splits `{a b & rest}` into the element patterns `a b` and the rest pattern `rest`.
+fn split_rest(
+    patterns: &im::Vector<Value>,
+) -> Result<(Vec<&Value>, Option<&Value>), anyhow::Error> {
+    let Some(position) = patterns
+        .iter()
+        .position(|v| matches!(v, Value::Sym(sym) if sym.as_str() == "&"))
+    else {
+        return Ok((patterns.iter().collect(), None));
+    };
+    let rest = patterns.iter().skip(position + 1).collect::<Vec<_>>();
+    let [rest] = rest[..] else {
+        return Err(anyhow::anyhow!(
+            "'&' must be followed by exactly one pattern"
+        ));
+    };
+    Ok((patterns.iter().take(position).collect(), Some(rest)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse;
+
+    /// Parses a single value, like `{x & xs}`.
+    fn value(input: &str) -> Value {
+        let Value::Sexpr(sexpr) = parse(input).unwrap() else {
+            unreachable!()
+        };
+        sexpr.0[0].clone()
+    }
+
+    fn bound(pattern: &str, input: &str) -> Option<Vec<String>> {
+        let mut bindings = HashMap::new();
+        bind(&value(pattern), &value(input), &mut bindings)
+            .unwrap()
+            .then(|| {
+                let mut bindings = bindings
+                    .into_iter()
+                    .map(|(k, v)| format!("{k}={v}"))
+                    .collect::<Vec<_>>();
+                bindings.sort();
+                bindings
+            })
+    }
+
+    #[test]
+    fn matches_literals_and_wildcards() {
+        assert_eq!(bound("1", "1"), Some(vec![]));
+        assert_eq!(bound("1", "2"), None);
+        assert_eq!(bound("_", "{1 2}"), Some(vec![]));
+        assert_eq!(bound("x", "{1 2}"), Some(vec!["x={ 1 2 }".to_string()]));
+    }
+
+    #[test]
+    fn matches_qexprs() {
+        assert_eq!(bound("{x 2}", "{1 2}"), Some(vec!["x=1".to_string()]));
+        assert_eq!(bound("{x 2}", "{1 3}"), None);
+        assert_eq!(bound("{x}", "{1 2}"), None);
+        assert_eq!(bound("{x}", "1"), None);
+    }
+
+    #[test]
+    fn matches_rest() {
+        assert_eq!(
+            bound("{x & xs}", "{1 2 3}"),
+            Some(vec!["x=1".to_string(), "xs={ 2 3 }".to_string()])
+        );
+        assert_eq!(
+            bound("{x & xs}", "{1}"),
+            Some(vec!["x=1".to_string(), "xs={}".to_string()])
+        );
+        assert_eq!(bound("{x & xs}", "{}"), None);
+        assert_eq!(
+            bound("{{a b} & _}", "{{1 2} 3}"),
+            Some(vec!["a=1".to_string(), "b=2".to_string()])
+        );
+    }
+
+    #[test]
+    fn matches_annotated_names_by_type() {
+        let typed = |pattern, input| {
+            matches(&value(pattern), &value(input))
+                .unwrap()
+                .map(|bindings| {
+                    bindings
+                        .into_keys()
+                        .map(|k| k.to_string())
+                        .collect::<Vec<_>>()
+                })
+        };
+        assert_eq!(typed("x:Num", "3"), Some(vec!["x".to_string()]));
+        assert_eq!(typed("x:Num", "{3}"), None);
+        assert_eq!(typed("{_ x:Qexpr}", "{1 {2}}"), Some(vec!["x".to_string()]));
+        assert!(matches(&value("x:Float"), &value("1")).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_rest() {
+        let mut bindings = HashMap::new();
+        assert!(bind(&value("{x & a b}"), &value("{1 2 3}"), &mut bindings).is_err());
+        assert!(bind(&value("&"), &value("1"), &mut bindings).is_err());
+    }
+}
@@ -1,11 +1,15 @@
-use crate::{environment::Environment, sexpr::Sexpr, value::Value};
+use crate::{
+    environment::Environment,
+    sexpr::Sexpr,
+    value::{Value, Values},
+};
 use anyhow::{Context, Error};
+use im::Vector;
 use itertools::Itertools;
 use serde_derive::{Deserialize, Serialize};
-use std::collections::VecDeque;
 
-#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
-pub struct Qexpr(pub(crate) VecDeque<Value>);
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub struct Qexpr(pub(crate) Vector<Value>);
 
 impl Qexpr {
     pub fn into_sexpr(self) -> Sexpr {
@@ -22,25 +26,27 @@ impl Qexpr {
     }
 
     pub fn join(self) -> Result<Value, anyhow::Error> {
-        let mut joined = VecDeque::new();
+        let mut joined = Vector::new();
         // TODO flat_map
         for child in self.0 {
-            let Value::Qexpr(mut child) = child else {
+            let Value::Qexpr(child) = child else {
                 return Err(anyhow::anyhow!("Join on non-qexpr"));
             };
-            joined.append(&mut child.0);
+            joined.append(child.0);
         }
         Ok(Value::Qexpr(Self(joined)))
     }
 
     pub fn eval(self, env: &mut Environment) -> Result<Value, Error> {
-        let sexpr = Sexpr(self.0);
-        sexpr.eval(env)
+        Value::Sexpr(Sexpr(self.0)).run(env)
     }
 }
 
 impl std::fmt::Display for Qexpr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "{{}}");
+        }
         write!(f, "{{")?;
         write!(f, " {} ", self.0.iter().join(" "))?;
         write!(f, "}}")?;
@@ -48,6 +54,33 @@ impl std::fmt::Display for Qexpr {
     }
 }
 
+impl Qexpr {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Value> {
+        self.0.get(index)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Value> {
+        self.0.iter()
+    }
+}
+
+impl IntoIterator for Qexpr {
+    type Item = Value;
+    type IntoIter = Values;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Values(self.0.into_iter())
+    }
+}
+
 impl FromIterator<Value> for Qexpr {
     fn from_iter<T: IntoIterator<Item = Value>>(iter: T) -> Self {
         Self(iter.into_iter().collect())
@@ -57,7 +90,7 @@ impl FromIterator<Value> for Qexpr {
 #[cfg(test)]
 mod test {
     use super::*;
-    use std::collections::VecDeque;
+    use crate::symbol::Symbol;
 
     #[test]
     fn head() {
@@ -115,7 +148,7 @@ mod test {
     #[test]
     fn into_sexpr() {
         let inner = [
-            Value::Sym("head".to_string()),
+            Value::Sym(Symbol::new("head")),
             Value::Qexpr(Qexpr::from_iter([
                 Value::Num(1),
                 Value::Num(2),
@@ -124,7 +157,7 @@ mod test {
             ])),
         ]
         .into_iter()
-        .collect::<VecDeque<_>>();
+        .collect::<Vector<_>>();
         let value = Qexpr(inner.clone());
         let result = Qexpr::into_sexpr(value);
         let expected = Sexpr(inner);
@@ -134,7 +167,7 @@ mod test {
     #[test]
     fn eval() {
         let value = Qexpr::from_iter([
-            Value::Sym("head".to_string()),
+            Value::Sym(Symbol::new("head")),
             Value::Qexpr(Qexpr::from_iter([
                 Value::Num(1),
                 Value::Num(2),
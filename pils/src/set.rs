@@ -0,0 +1,110 @@
+use crate::value::Value;
+use im::OrdSet;
+use itertools::Itertools;
+use serde_derive::{Deserialize, Serialize};
+
+/// An unordered collection of distinct values.
+/// It is stored sorted so that it always displays the same way.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Set(pub(crate) OrdSet<Value>);
+
+impl Set {
+    pub fn union(self, others: impl IntoIterator<Item = Self>) -> Value {
+        Value::Set(Self(OrdSet::unions(
+            std::iter::once(self.0).chain(others.into_iter().map(|s| s.0)),
+        )))
+    }
+
+    pub fn intersection(self, others: impl IntoIterator<Item = Self>) -> Value {
+        let intersection = others
+            .into_iter()
+            .fold(self.0, |acc, other| acc.intersection(other.0));
+        Value::Set(Self(intersection))
+    }
+
+    pub fn difference(self, others: impl IntoIterator<Item = Self>) -> Value {
+        let difference = others
+            .into_iter()
+            .fold(self.0, |acc, other| acc.relative_complement(other.0));
+        Value::Set(Self(difference))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn contains(&self, value: &Value) -> bool {
+        self.0.contains(value)
+    }
+
+    /// The values, in order.
+    pub fn iter(&self) -> impl Iterator<Item = &Value> {
+        self.0.iter()
+    }
+
+    pub fn member(&self, value: &Value) -> Value {
+        Value::Num(i64::from(self.0.contains(value)))
+    }
+}
+
+impl std::fmt::Display for Set {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "#[]");
+        }
+        write!(f, "#[")?;
+        write!(f, " {} ", self.0.iter().join(" "))?;
+        write!(f, "]")?;
+        Ok(())
+    }
+}
+
+impl FromIterator<Value> for Set {
+    fn from_iter<T: IntoIterator<Item = Value>>(iter: T) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::symbol::Symbol;
+
+    fn nums(ns: &[i64]) -> Set {
+        ns.iter().copied().map(Value::Num).collect()
+    }
+
+    #[test]
+    fn deduplicates_and_sorts() {
+        assert_eq!(format!("{}", nums(&[3, 1, 2, 3, 1])), "#[ 1 2 3 ]");
+    }
+
+    #[test]
+    fn union() {
+        let result = nums(&[1, 2]).union([nums(&[2, 3]), nums(&[5])]);
+        assert_eq!(result, Value::Set(nums(&[1, 2, 3, 5])));
+    }
+
+    #[test]
+    fn intersection() {
+        let result = nums(&[1, 2, 3, 4]).intersection([nums(&[2, 3, 4]), nums(&[1, 3, 4])]);
+        assert_eq!(result, Value::Set(nums(&[3, 4])));
+    }
+
+    #[test]
+    fn difference() {
+        let result = nums(&[1, 2, 3, 4]).difference([nums(&[2]), nums(&[4, 5])]);
+        assert_eq!(result, Value::Set(nums(&[1, 3])));
+    }
+
+    #[test]
+    fn member() {
+        let set = Set::from_iter([Value::Sym(Symbol::new("a")), Value::Num(1)]);
+        assert_eq!(set.member(&Value::Sym(Symbol::new("a"))), Value::Num(1));
+        assert_eq!(set.member(&Value::Num(2)), Value::Num(0));
+    }
+}
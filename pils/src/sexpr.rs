@@ -1,21 +1,33 @@
-use crate::{environment::Environment, value::Value};
+use crate::{
+    environment::Environment,
+    value::{Value, Values},
+};
+use im::Vector;
 use itertools::Itertools;
 use serde_derive::{Deserialize, Serialize};
-use std::collections::VecDeque;
 
-#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
-pub struct Sexpr(pub(crate) VecDeque<Value>);
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub struct Sexpr(pub(crate) Vector<Value>);
 
 impl Sexpr {
     pub fn eval(self, env: &mut Environment) -> Result<Value, anyhow::Error> {
-        let mut evaluated = self
-            .0
-            .into_iter()
-            .map(|v| v.eval(env))
-            .collect::<Result<VecDeque<_>, anyhow::Error>>()?;
+        env.step()?;
+        env.nested(|env| {
+            let evaluated = self
+                .0
+                .into_iter()
+                .map(|v| v.eval(env))
+                .collect::<Result<Vector<_>, anyhow::Error>>()?;
+            Self(evaluated).apply(env)
+        })
+    }
 
+    /// Applies an s-expression whose elements have already been evaluated:
+    /// the first element is called with the remaining ones as arguments.
+    pub(crate) fn apply(self, env: &mut Environment) -> Result<Value, anyhow::Error> {
+        let mut evaluated = self.0;
         if evaluated.is_empty() {
-            return Ok(Value::Sexpr(Self(VecDeque::default())));
+            return Ok(Value::Sexpr(Self(Vector::new())));
         }
 
         if evaluated.len() == 1 {
@@ -26,7 +38,9 @@ impl Sexpr {
             return Err(anyhow::anyhow!("First element is not a function"));
         };
 
-        fun.0(Value::Sexpr(Self(evaluated)), env)
+        let result = fun.call(Value::Sexpr(Self(evaluated)), env)?;
+        env.reserve(&result)?;
+        Ok(result)
     }
 
     pub fn add(self) -> Result<Value, anyhow::Error> {
@@ -38,7 +52,7 @@ impl Sexpr {
                 _ => Err(anyhow::anyhow!("'add' on non-number")),
             })
             .collect::<Result<Vec<_>, _>>()?;
-        Ok(Value::Num(n.into_iter().sum()))
+        Ok(Value::Num(sum(&n)?))
     }
 
     pub fn sub(self) -> Result<Value, anyhow::Error> {
@@ -50,7 +64,7 @@ impl Sexpr {
                 _ => Err(anyhow::anyhow!("'sub' on non-number")),
             })
             .collect::<Result<Vec<_>, _>>()?;
-        Ok(Value::Num(n.into_iter().fold(0, |acc, val| acc - val)))
+        Ok(Value::Num(difference(&n)?))
     }
 
     pub fn mul(self) -> Result<Value, anyhow::Error> {
@@ -62,7 +76,7 @@ impl Sexpr {
                 _ => Err(anyhow::anyhow!("'mul' on non-number")),
             })
             .collect::<Result<Vec<_>, _>>()?;
-        Ok(Value::Num(n.into_iter().product()))
+        Ok(Value::Num(product(&n)?))
     }
 
     pub fn div(self) -> Result<Value, anyhow::Error> {
@@ -75,15 +89,103 @@ impl Sexpr {
                 _ => Err(anyhow::anyhow!("'div' on non-number")),
             })
             .collect::<Result<Vec<_>, _>>()?;
-        // TODO elegantify
-        if n.len() == 1 {
-            return Ok(Value::Num(n[0]));
+        Ok(Value::Num(quotient(&n)?))
+    }
+}
+
+fn overflow() -> anyhow::Error {
+    anyhow::anyhow!("Integer overflow")
+}
+
+fn sum(n: &[i64]) -> Result<i64, anyhow::Error> {
+    n.iter()
+        .try_fold(0_i64, |acc, val| acc.checked_add(*val))
+        .ok_or_else(overflow)
+}
+
+fn difference(n: &[i64]) -> Result<i64, anyhow::Error> {
+    n.iter()
+        .try_fold(0_i64, |acc, val| acc.checked_sub(*val))
+        .ok_or_else(overflow)
+}
+
+fn product(n: &[i64]) -> Result<i64, anyhow::Error> {
+    n.iter()
+        .try_fold(1_i64, |acc, val| acc.checked_mul(*val))
+        .ok_or_else(overflow)
+}
+
+fn quotient(n: &[i64]) -> Result<i64, anyhow::Error> {
+    if n.contains(&0) {
+        return Err(anyhow::anyhow!("Division by zero"));
+    }
+    // TODO elegantify
+    if n.len() == 1 {
+        return Ok(n[0]);
+    }
+    let mut fst = n[0];
+    for val in n.iter().skip(1) {
+        fst = fst.checked_div(*val).ok_or_else(overflow)?;
+    }
+    Ok(fst)
+}
+
+/// The arithmetic of the numeric builtins, working on plain numbers.
+/// This lets callers that already know all arguments are numbers skip building an s-expression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Arithmetic {
+    Sum,
+    Difference,
+    Product,
+    Quotient,
+}
+
+impl Arithmetic {
+    /// The arithmetic of the builtin with this name, if it is a numeric one.
+    pub(crate) fn of(name: &str) -> Option<Self> {
+        match name {
+            "+" => Some(Self::Sum),
+            "-" => Some(Self::Difference),
+            "*" => Some(Self::Product),
+            "/" => Some(Self::Quotient),
+            _ => None,
         }
-        let mut fst = n[0];
-        for val in n.iter().skip(1) {
-            fst /= val;
+    }
+
+    pub(crate) fn apply(self, n: &[i64]) -> Result<i64, anyhow::Error> {
+        match self {
+            Self::Sum => sum(n),
+            Self::Difference => difference(n),
+            Self::Product => product(n),
+            Self::Quotient => quotient(n),
         }
-        Ok(Value::Num(fst))
+    }
+}
+
+impl Sexpr {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Value> {
+        self.0.get(index)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Value> {
+        self.0.iter()
+    }
+}
+
+impl IntoIterator for Sexpr {
+    type Item = Value;
+    type IntoIter = Values;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Values(self.0.into_iter())
     }
 }
 
@@ -95,6 +197,9 @@ impl FromIterator<Value> for Sexpr {
 
 impl std::fmt::Display for Sexpr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "()");
+        }
         write!(f, "(")?;
         write!(f, " {} ", self.0.iter().join(" "))?;
         write!(f, ")")?;
@@ -105,11 +210,12 @@ impl std::fmt::Display for Sexpr {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::symbol::Symbol;
 
     #[test]
     fn sexpr_multiplication() {
         let mut env = Environment::default();
-        let mul = env.0.get("*").unwrap();
+        let mul = env.get(Symbol::new("*")).unwrap();
 
         let operands = Sexpr::from_iter([mul.clone(), Value::Num(1), Value::Num(2), Value::Num(4)]);
         let num = operands.eval(&mut env).unwrap();
@@ -119,7 +225,7 @@ mod test {
     #[test]
     fn division() {
         let mut env = Environment::default();
-        let div = env.0.get("/").unwrap();
+        let div = env.get(Symbol::new("/")).unwrap();
         let operands =
             Sexpr::from_iter([div.clone(), Value::Num(12), Value::Num(1), Value::Num(4)]);
         assert!(matches!(operands.eval(&mut env).unwrap(), Value::Num(3)));
@@ -128,7 +234,7 @@ mod test {
     #[test]
     fn rejects_to_divide_by_zero() {
         let mut env = Environment::default();
-        let div = env.0.get("/").unwrap();
+        let div = env.get(Symbol::new("/")).unwrap();
         let operands =
             Sexpr::from_iter([div.clone(), Value::Num(12), Value::Num(0), Value::Num(4)]);
         let result = operands.eval(&mut env).unwrap_err();
@@ -138,7 +244,7 @@ mod test {
     #[test]
     fn unary_minus() {
         let mut env = Environment::default();
-        let sub = env.0.get("-").unwrap();
+        let sub = env.get(Symbol::new("-")).unwrap();
         let operands = Sexpr::from_iter([sub.clone(), Value::Num(12)]);
         assert!(matches!(operands.eval(&mut env).unwrap(), Value::Num(-12)));
     }
@@ -0,0 +1,154 @@
+use serde::{de, Deserializer, Serializer};
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::{LazyLock, RwLock};
+
+/// Every symbol text seen so far, stored once for the lifetime of the program.
+///
+/// Interned names are never freed, so untrusted input creating fresh symbols, like
+/// `json-parse` on objects with new keys, grows this table for good. The bytes each thread
+/// interns are counted, and interpreters charge them to their memory usage.
+#[derive(Default)]
+struct Interner {
+    ids: HashMap<&'static str, Symbol>,
+    names: Vec<&'static str>,
+}
+
+static INTERNER: LazyLock<RwLock<Interner>> = LazyLock::new(|| RwLock::new(Interner::default()));
+
+thread_local! {
+    static INTERNED_BYTES: Cell<usize> = const { Cell::new(0) };
+}
+
+/// The bytes interned by this thread so far, counting the name and its entries in the table.
+pub(crate) fn interned_bytes() -> usize {
+    INTERNED_BYTES.with(Cell::get)
+}
+
+/// An interned symbol: a small handle that is cheap to copy, compare and hash.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    pub fn new(name: &str) -> Self {
+        if let Some(sym) = INTERNER.read().unwrap().ids.get(name) {
+            return *sym;
+        }
+        let mut interner = INTERNER.write().unwrap();
+        if let Some(sym) = interner.ids.get(name) {
+            return *sym;
+        }
+        let sym = Self(u32::try_from(interner.names.len()).expect("too many symbols"));
+        let name: &'static str = Box::leak(name.into());
+        interner.names.push(name);
+        interner.ids.insert(name, sym);
+        let bytes = name.len() + 2 * std::mem::size_of::<&str>() + std::mem::size_of::<Self>();
+        INTERNED_BYTES.with(|interned| interned.set(interned.get() + bytes));
+        sym
+    }
+
+    pub fn as_str(self) -> &'static str {
+        INTERNER.read().unwrap().names[self.0 as usize]
+    }
+
+    /// Whether `text` is read back as a symbol, rather than as a number or not at all.
+    pub(crate) fn is_readable(text: &str) -> bool {
+        let number = text
+            .strip_prefix('-')
+            .unwrap_or(text)
+            .starts_with(|c: char| c.is_ascii_digit());
+        !text.is_empty()
+            && !number
+            && text
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "_+-*/=<>!&?:".contains(c))
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Symbols order by their text, not by the order in which they were interned.
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> Ordering {
+        if self == other {
+            return Ordering::Equal;
+        }
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Self::new(name)
+    }
+}
+
+impl std::fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+impl std::fmt::Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl serde::Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Symbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SymbolVisitor;
+
+        impl<'de> de::Visitor<'de> for SymbolVisitor {
+            type Value = Symbol;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "a symbol")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Symbol, E> {
+                Ok(Symbol::new(v))
+            }
+        }
+
+        deserializer.deserialize_str(SymbolVisitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn interns_once() {
+        assert_eq!(Symbol::new("interned"), Symbol::new("interned"));
+        assert_ne!(Symbol::new("interned"), Symbol::new("other"));
+        assert_eq!(Symbol::new("interned").as_str(), "interned");
+    }
+
+    #[test]
+    fn orders_by_text() {
+        let b = Symbol::new("zzz-interned-first");
+        let a = Symbol::new("aaa-interned-second");
+        assert!(a < b);
+    }
+
+    #[test]
+    fn serializes_as_text() {
+        let sym = Symbol::new("round-trip");
+        let json = serde_json::to_string(&sym).unwrap();
+        assert_eq!(json, r#""round-trip""#);
+        assert_eq!(serde_json::from_str::<Symbol>(&json).unwrap(), sym);
+    }
+}
@@ -21,7 +21,7 @@ fn process_join() {
 
 #[test]
 fn example_11a() {
-    assert_eq!(process_str("+"), "<function>");
+    assert_eq!(process_str("+"), "<builtin +>");
 }
 
 #[test]
@@ -46,14 +46,15 @@ fn process_eval() {
 
 #[test]
 fn process_def() {
-    let _ = process_str("def {x} 100");
-    let _ = process_str("def {y} 200");
-    assert_eq!(process_str("+ x y"), "300".to_string());
-    let _ = process_str("def {a b} 5 6");
-    assert_eq!(process_str("+ a b"), "11".to_string());
-    let _ = process_str("def {arglist} {a b x y}");
-    let _ = process_str("def arglist 1 2 3 4");
-    assert_eq!(process_str("list a b x y"), "{ 1 2 3 4 }".to_string());
+    let mut pils = Interpreter::default();
+    let _ = pils.process_str("def {x} 100");
+    let _ = pils.process_str("def {y} 200");
+    assert_eq!(pils.process_str("+ x y"), "300".to_string());
+    let _ = pils.process_str("def {a b} 5 6");
+    assert_eq!(pils.process_str("+ a b"), "11".to_string());
+    let _ = pils.process_str("def {arglist} {a b x y}");
+    let _ = pils.process_str("def arglist 1 2 3 4");
+    assert_eq!(pils.process_str("list a b x y"), "{ 1 2 3 4 }".to_string());
 }
 
 #[test]
@@ -67,3 +68,209 @@ fn displays() {
     let result = format!("{val}");
     assert_eq!(format!("( {line} )"), result);
 }
+
+#[test]
+fn process_map_literal() {
+    assert_eq!(process_str("#{ b 2 a 1 }"), "#{ a 1 b 2 }");
+    assert_eq!(process_str("get #{ a 1 b 2 } {b}"), "2");
+    assert_eq!(process_str("get #{ 1 {x y} } 1"), "{ x y }");
+    assert_eq!(process_str("has? #{ a 1 } {b}"), "0");
+    assert_eq!(process_str("#{}"), "#{}");
+    assert_eq!(process_str("dissoc #{ a 1 } {a}"), "#{}");
+    assert_eq!(
+        process_str("get #{ a 1 } {b}"),
+        "Error: key 'b' not found in map"
+    );
+}
+
+#[test]
+fn process_map_builtins() {
+    let mut pils = Interpreter::default();
+    let _ = pils.process_str("def {m} #{ x 1 y 2 }");
+    assert_eq!(pils.process_str("assoc m {z x} 3 4"), "#{ x 4 y 2 z 3 }");
+    assert_eq!(pils.process_str("dissoc m {x}"), "#{ y 2 }");
+    assert_eq!(pils.process_str("keys m"), "{ x y }");
+    assert_eq!(pils.process_str("vals m"), "{ 1 2 }");
+    assert_eq!(pils.process_str("has? (assoc m {q} 0) {q}"), "1");
+    assert!(pils.get_env_json().contains(r#""m": {"#));
+}
+
+#[test]
+fn process_sets() {
+    assert_eq!(process_str("set 3 {b} 1 {a} 3"), "#[ 1 3 { a } { b } ]");
+    assert_eq!(process_str("union (set 1 2) (set 2 3)"), "#[ 1 2 3 ]");
+    assert_eq!(
+        process_str("intersection (set 1 2 3) (set 2 3 4) (set 3 4)"),
+        "#[ 3 ]"
+    );
+    assert_eq!(process_str("difference (set 1 2 3) (set 2)"), "#[ 1 3 ]");
+    assert_eq!(process_str("difference (set 1) (set 1)"), "#[]");
+    assert_eq!(process_str("member? (set 1 2 3) 2"), "1");
+    assert_eq!(process_str("member? (set 1 2 3) {2}"), "0");
+    assert_eq!(
+        process_str("union (set 1) {1}"),
+        "Error: Function 'union' passed non-set"
+    );
+}
+
+#[test]
+fn process_overflow() {
+    assert_eq!(
+        process_str("+ 9223372036854775807 1"),
+        "Error: Integer overflow"
+    );
+    assert_eq!(
+        process_str("* 4611686018427387904 2"),
+        "Error: Integer overflow"
+    );
+    assert_eq!(
+        process_str("- 0 -9223372036854775808"),
+        "Error: Integer overflow"
+    );
+    assert_eq!(
+        process_str("/ -9223372036854775808 -1"),
+        "Error: Integer overflow"
+    );
+}
+
+#[test]
+fn folding_keeps_errors_at_runtime() {
+    assert_eq!(process_str("+ 1 (/ 10 0)"), "Error: Division by zero");
+    assert_eq!(process_str("eval {head {1 2} 3}"), "1");
+}
+
+#[test]
+fn process_type_annotations() {
+    let mut pils = Interpreter::default();
+    assert_eq!(pils.process_str("def {tn:Num tq:Qexpr} 1 {2 3}"), "()");
+    assert_eq!(pils.process_str("+ tn (head tq)"), "3");
+    assert_eq!(
+        pils.process_str("def {tn:Num} {1}"),
+        "Error: 'tn' is declared as Num, but bound to Qexpr"
+    );
+    assert_eq!(pils.process_str("tn"), "1");
+    assert_eq!(
+        pils.process_str("redef {tn} {1}"),
+        "Error: 'tn' is declared as Num, but bound to Qexpr"
+    );
+    assert_eq!(pils.process_str("def {tn:Qexpr} {1}"), "()");
+    assert_eq!(pils.process_str("tn"), "{ 1 }");
+    assert_eq!(
+        pils.process_str("def {tx:Float} 1"),
+        "Error: unknown type 'Float'"
+    );
+    assert_eq!(
+        pils.process_str("def {tx tx:Num} 1 2"),
+        "Error: 'def' binds 'tx' more than once"
+    );
+    assert_eq!(
+        pils.process_str("def {tx {ty tx}} 1 {2 3}"),
+        "Error: 'def' binds 'tx' more than once"
+    );
+    assert_eq!(pils.process_str("tx"), "Error: unbound symbol");
+}
+
+#[test]
+fn process_match() {
+    let mut pils = Interpreter::default();
+    assert_eq!(
+        pils.process_str("match (list 1 2 3) {{} 0 {mx & mxs} {list mx mxs}}"),
+        "{ 1 { 2 3 } }"
+    );
+    assert_eq!(pils.process_str("match 2 {1 {+ 10} 2 {+ 20} _ 0}"), "20");
+    assert_eq!(pils.process_str("match 5 {1 10 mn {* mn mn}}"), "25");
+    assert_eq!(pils.process_str("mn"), "Error: unbound symbol");
+    assert_eq!(pils.process_str("match 3 {mn:Qexpr 0 mn:Num mn}"), "3");
+    assert_eq!(
+        pils.process_str("match {1 2} {{a} a 3 a}"),
+        "Error: No arm of 'match' matches { 1 2 }"
+    );
+}
+
+#[test]
+fn process_def_destructuring() {
+    let mut pils = Interpreter::default();
+    assert_eq!(pils.process_str("def {da {db dc}} 1 {2 3}"), "()");
+    assert_eq!(pils.process_str("list da db dc"), "{ 1 2 3 }");
+    assert_eq!(pils.process_str("def {{dd & ds}} {4 5 6}"), "()");
+    assert_eq!(pils.process_str("list dd ds"), "{ 4 { 5 6 } }");
+    assert_eq!(
+        pils.process_str("def {de df dg} 1"),
+        "Error: 'def' got 3 names but 1 values"
+    );
+    assert_eq!(
+        pils.process_str("def {de {df dg}} 1 {2}"),
+        "Error: 'def' cannot destructure { 2 } into { df dg }"
+    );
+    assert_eq!(pils.process_str("de"), "Error: unbound symbol");
+    assert_eq!(
+        pils.process_str("def {de 1} 1 2"),
+        "Error: 'def' cannot define non-symbol"
+    );
+}
+
+#[test]
+fn process_constants() {
+    let mut pils = Interpreter::default();
+    assert_eq!(
+        pils.process_str("def {+} 5"),
+        "Error: '+' is constant, use 'redef' to redefine it"
+    );
+    assert_eq!(pils.process_str("+ 1 2"), "3");
+    assert_eq!(pils.process_str("def {ca cb} 1 2"), "()");
+    assert_eq!(pils.process_str("const {ca}"), "()");
+    assert_eq!(
+        pils.process_str("def {cb ca} 3 4"),
+        "Error: 'ca' is constant, use 'redef' to redefine it"
+    );
+    assert_eq!(pils.process_str("list ca cb"), "{ 1 2 }");
+    assert_eq!(pils.process_str("redef {ca} 5"), "()");
+    assert_eq!(pils.process_str("ca"), "5");
+    assert_eq!(
+        pils.process_str("const {cz}"),
+        "Error: 'const' on undefined 'cz'"
+    );
+}
+
+#[test]
+fn process_json() {
+    assert_eq!(process_str(r#""a \"b\"""#), r#""a \"b\"""#);
+    assert_eq!(
+        process_str(r#"json-parse "{\"x\": [1, \"two\", true]}""#),
+        r#"#{ x { 1 "two" 1 } }"#
+    );
+    assert_eq!(
+        process_str("json-emit #{ a {1 2} b text }"),
+        r#""{\"a\":[1,2],\"b\":\"text\"}""#
+    );
+    assert_eq!(
+        process_str(r#"json-parse "{\"a b\": 1, \"\": 2, \"c\": 3}""#),
+        r#"#{ c 3 "" 2 "a b" 1 }"#
+    );
+    assert_eq!(process_str(r#"get #{ "a b" 1 } "a b""#), "1");
+    assert_eq!(process_str(r#"json-emit #{ "a b" 1 }"#), r#""{\"a b\":1}""#);
+    assert_eq!(
+        process_str("json-parse 5"),
+        "Error: Function 'json-parse' passed non-string"
+    );
+    assert_eq!(
+        process_str(r#"json-parse "[1,""#),
+        "Error: 'json-parse' passed invalid JSON"
+    );
+    assert_eq!(
+        process_str(r#"json-parse "{\"a\": 1, \"a\": 2}""#),
+        "Error: 'json-parse' passed invalid JSON"
+    );
+    assert_eq!(
+        process_str(r#"json-parse "{\"7\": 1, \"007\": 2}""#),
+        r#"#{ 7 1 "007" 2 }"#
+    );
+}
+
+#[test]
+fn process_rejects_duplicate_map_keys() {
+    assert_eq!(
+        process_str("{ #{ a 1 b 2 a 3 } }"),
+        "Error: duplicate map key 'a'"
+    );
+}
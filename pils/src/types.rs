@@ -0,0 +1,329 @@
+//! Optional type annotations, like `def {x:Num xs:Qexpr} 1 {2 3}`,
+//! and a checker that finds type mismatches before evaluation.
+
+use crate::{environment::Environment, qexpr::Qexpr, sexpr::Sexpr, symbol::Symbol, value::Value};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The type of a value. Every kind of value is a type, and `Any` stands for unknown types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Type {
+    Any,
+    Num,
+    Sym,
+    Str,
+    Sexpr,
+    Qexpr,
+    Map,
+    Set,
+    Fun,
+}
+
+impl Type {
+    pub fn of(value: &Value) -> Self {
+        match value {
+            Value::Num(_) => Self::Num,
+            Value::Sym(_) => Self::Sym,
+            Value::Str(_) => Self::Str,
+            Value::Sexpr(_) => Self::Sexpr,
+            Value::Qexpr(_) => Self::Qexpr,
+            Value::Map(_) => Self::Map,
+            Value::Set(_) => Self::Set,
+            Value::Fun(_) => Self::Fun,
+        }
+    }
+
+    /// Whether a value of type `other` may be used where `self` is expected.
+    /// Unknown types are consistent with everything.
+    pub fn accepts(self, other: Self) -> bool {
+        self == Self::Any || other == Self::Any || self == other
+    }
+}
+
+impl std::str::FromStr for Type {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "Any" => Self::Any,
+            "Num" => Self::Num,
+            "Sym" => Self::Sym,
+            "Str" => Self::Str,
+            "Sexpr" => Self::Sexpr,
+            "Qexpr" => Self::Qexpr,
+            "Map" => Self::Map,
+            "Set" => Self::Set,
+            "Fun" => Self::Fun,
+            _ => return Err(anyhow::anyhow!("unknown type '{s}'")),
+        })
+    }
+}
+
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// Splits a parameter like `x:Num` into its name and declared type.
+/// Parameters without annotation, like `x`, have no declared type.
+pub(crate) fn parse_param(param: Symbol) -> Result<(Symbol, Option<Type>), anyhow::Error> {
+    match param.as_str().split_once(':') {
+        Some((name, ty)) => Ok((Symbol::new(name), Some(ty.parse()?))),
+        None => Ok((param, None)),
+    }
+}
+
+/// Fails if a value does not have the type declared for the name it is bound to.
+pub(crate) fn check_binding(name: Symbol, ty: Type, value: &Value) -> Result<(), anyhow::Error> {
+    if ty.accepts(Type::of(value)) {
+        return Ok(());
+    }
+    Err(anyhow::anyhow!(
+        "'{name}' is declared as {ty}, but bound to {}",
+        Type::of(value)
+    ))
+}
+
+/// A mismatch between the type something is used as and the type it has.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    /// The code where the mismatch occurs.
+    pub context: String,
+    pub expected: Type,
+    pub found: Type,
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "'{}' expected {}, found {}",
+            self.context, self.expected, self.found
+        )
+    }
+}
+
+/// The parameter and result types of a builtin.
+struct Signature {
+    params: &'static [Type],
+    /// The type of any arguments after `params`, if more are allowed.
+    rest: Option<Type>,
+    returns: Type,
+}
+
+fn signature(builtin: &str) -> Option<Signature> {
+    use Type::*;
+    let (params, rest, returns): (&'static [Type], _, _) = match builtin {
+        "+" | "-" | "*" | "/" => (&[Num], Some(Num), Num),
+        "list" => (&[], Some(Any), Qexpr),
+        "head" | "eval" => (&[Qexpr], None, Any),
+        "match" => (&[Any, Qexpr], None, Any),
+        "tail" | "join" => (&[Qexpr], None, Qexpr),
+        "get" => (&[Map, Any], None, Any),
+        "assoc" => (&[Map, Any], Some(Any), Map),
+        "dissoc" => (&[Map, Any], None, Map),
+        "keys" | "vals" => (&[Map], None, Qexpr),
+        "has?" => (&[Map, Any], None, Num),
+        "set" => (&[], Some(Any), Set),
+        "union" | "intersection" | "difference" => (&[Set], Some(Set), Set),
+        "member?" => (&[Set, Any], None, Num),
+        "json-parse" => (&[Str], None, Any),
+        "json-emit" => (&[Any], None, Str),
+        _ => return None,
+    };
+    Some(Signature {
+        params,
+        rest,
+        returns,
+    })
+}
+
+/// Infers types of a program without evaluating it, reporting every mismatch found.
+pub fn check(program: &Value, env: &Environment) -> Result<Type, Vec<Mismatch>> {
+    let mut checker = Checker {
+        env,
+        declared: env.declared.clone(),
+        mismatches: Vec::new(),
+    };
+    let ty = checker.infer(program);
+    if checker.mismatches.is_empty() {
+        Ok(ty)
+    } else {
+        Err(checker.mismatches)
+    }
+}
+
+struct Checker<'a> {
+    env: &'a Environment,
+    /// Declared types, including those of definitions checked so far.
+    declared: HashMap<Symbol, Type>,
+    mismatches: Vec<Mismatch>,
+}
+
+impl Checker<'_> {
+    fn infer(&mut self, value: &Value) -> Type {
+        match value {
+            Value::Sym(sym) => match (self.declared.get(sym), self.env.get(*sym)) {
+                (Some(ty), _) => *ty,
+                (None, Some(Value::Fun(_))) => Type::Fun,
+                _ => Type::Any,
+            },
+            Value::Sexpr(sexpr) => self.call(sexpr),
+            v => Type::of(v),
+        }
+    }
+
+    fn call(&mut self, sexpr: &Sexpr) -> Type {
+        let types = sexpr.0.iter().map(|v| self.infer(v)).collect::<Vec<_>>();
+        match &types[..] {
+            [] => return Type::Sexpr,
+            [ty] => return *ty,
+            _ => {}
+        }
+        let Some(Value::Sym(operator)) = sexpr.0.front() else {
+            return Type::Any;
+        };
+        if self.declared.contains_key(operator) {
+            return Type::Any;
+        }
+        let Some(Value::Fun(fun)) = self.env.get(*operator) else {
+            return Type::Any;
+        };
+        if fun.name == "def" || fun.name == "redef" {
+            self.def(sexpr, &types[1..]);
+            return Type::Any;
+        }
+        if fun.name == "eval" {
+            if let Some(Value::Qexpr(Qexpr(code))) = sexpr.0.get(1) {
+                return self.call(&Sexpr(code.clone()));
+            }
+        }
+        let Some(signature) = signature(fun.name) else {
+            return Type::Any;
+        };
+        for (i, found) in types[1..].iter().enumerate() {
+            let expected = signature.params.get(i).copied().or(signature.rest);
+            match expected {
+                Some(expected) if !expected.accepts(*found) => {
+                    self.mismatches.push(Mismatch {
+                        context: sexpr.to_string(),
+                        expected,
+                        found: *found,
+                    });
+                }
+                _ => {}
+            }
+        }
+        signature.returns
+    }
+
+    fn def(&mut self, sexpr: &Sexpr, types: &[Type]) {
+        let Some(Value::Qexpr(Qexpr(params))) = sexpr.0.get(1) else {
+            return;
+        };
+        for (param, found) in params.iter().zip(&types[1..]) {
+            let Value::Sym(param) = param else {
+                continue;
+            };
+            let Ok((name, declared)) = parse_param(*param) else {
+                continue;
+            };
+            match declared {
+                Some(expected) if !expected.accepts(*found) => {
+                    self.mismatches.push(Mismatch {
+                        context: sexpr.to_string(),
+                        expected,
+                        found: *found,
+                    });
+                }
+                Some(expected) => {
+                    self.declared.insert(name, expected);
+                }
+                None => {
+                    self.declared.remove(&name);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse;
+
+    fn checked(program: &str) -> Result<Type, Vec<String>> {
+        check(&parse(program).unwrap(), &Environment::default())
+            .map_err(|m| m.into_iter().map(|m| m.to_string()).collect())
+    }
+
+    #[test]
+    fn parses_annotations() {
+        assert_eq!(
+            parse_param(Symbol::new("xs:Qexpr")).unwrap(),
+            (Symbol::new("xs"), Some(Type::Qexpr))
+        );
+        assert_eq!(
+            parse_param(Symbol::new("x")).unwrap(),
+            (Symbol::new("x"), None)
+        );
+        assert!(parse_param(Symbol::new("x:Float")).is_err());
+    }
+
+    #[test]
+    fn infers_builtin_results() {
+        assert_eq!(checked("+ 1 (* 2 3)"), Ok(Type::Num));
+        assert_eq!(checked("list 1 2"), Ok(Type::Qexpr));
+        assert_eq!(checked("head {1 2}"), Ok(Type::Any));
+        assert_eq!(checked("has? #{ a 1 } {a}"), Ok(Type::Num));
+        assert_eq!(checked("eval {tail {1 2}}"), Ok(Type::Qexpr));
+    }
+
+    #[test]
+    fn reports_mismatched_arguments() {
+        assert_eq!(
+            checked("+ 1 (list 2)"),
+            Err(vec![
+                "'( + 1 ( list 2 ) )' expected Num, found Qexpr".to_string()
+            ])
+        );
+        assert_eq!(
+            checked("union (set 1) 2"),
+            Err(vec![
+                "'( union ( set 1 ) 2 )' expected Set, found Num".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn unknown_types_are_accepted() {
+        assert_eq!(checked("+ x (head {1})"), Ok(Type::Num));
+    }
+
+    #[test]
+    fn checks_annotated_definitions() {
+        assert_eq!(checked("def {x:Num xs:Qexpr} 1 {2 3}"), Ok(Type::Any));
+        assert_eq!(
+            checked("def {x:Num} {1}"),
+            Err(vec![
+                "'( def { x:Num } { 1 } )' expected Num, found Qexpr".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn uses_declared_types() {
+        let mut env = Environment::default();
+        env.declared.insert(Symbol::new("n"), Type::Num);
+        let result = check(&parse("head n").unwrap(), &env);
+        assert_eq!(
+            result,
+            Err(vec![Mismatch {
+                context: "( head n )".to_string(),
+                expected: Type::Qexpr,
+                found: Type::Num,
+            }])
+        );
+    }
+}
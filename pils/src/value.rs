@@ -1,27 +1,172 @@
 use crate::environment::Environment;
 use crate::function::Function;
+use crate::map::{Key, Map};
 use crate::parser::Rule;
+use crate::set::Set;
+use crate::symbol::Symbol;
 use crate::{qexpr::Qexpr, sexpr::Sexpr};
 use anyhow::Context;
+use im::Vector;
 use pest::iterators::Pair;
 use serde_derive::{Deserialize, Serialize};
-use std::collections::VecDeque;
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// A pils value.
+///
+/// Values are totally ordered, so they can be sorted and kept in sets or used as map keys.
+/// Values of different kinds order by kind, in the order the variants are declared:
+/// numbers < symbols < strings < s-expressions < q-expressions < maps < sets < functions.
+/// Values of the same kind compare by their contents, element by element for
+/// expressions and sets, entry by entry for maps, and by name for functions.
+///
+/// `Display` writes values in pils syntax, as the REPL shows them. Except for sets and
+/// functions, which display like `<builtin head>`, the output parses back into an equal value with [`str::parse`].
+/// `Debug` shows the Rust structure and is not meant to be stable.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum Value {
     Num(i64),
-    Sym(String),
+    Sym(Symbol),
+    Str(String),
     Sexpr(Sexpr),
     Qexpr(Qexpr),
-    #[serde(skip)]
+    Map(Map),
+    Set(Set),
     Fun(Function),
 }
 
 impl Value {
+    pub fn num(n: i64) -> Self {
+        Self::Num(n)
+    }
+
+    pub fn sym(name: &str) -> Self {
+        Self::Sym(Symbol::new(name))
+    }
+
+    pub fn str(text: &str) -> Self {
+        Self::Str(text.to_string())
+    }
+
+    pub fn sexpr(values: impl IntoIterator<Item = Self>) -> Self {
+        Self::Sexpr(values.into_iter().collect())
+    }
+
+    pub fn qexpr(values: impl IntoIterator<Item = Self>) -> Self {
+        Self::Qexpr(values.into_iter().collect())
+    }
+
+    pub fn map(entries: impl IntoIterator<Item = (Key, Self)>) -> Self {
+        Self::Map(entries.into_iter().collect())
+    }
+
+    pub fn set(values: impl IntoIterator<Item = Self>) -> Self {
+        Self::Set(values.into_iter().collect())
+    }
+
+    pub fn as_num(&self) -> Option<i64> {
+        match self {
+            Self::Num(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_sym(&self) -> Option<Symbol> {
+        match self {
+            Self::Sym(sym) => Some(*sym),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_sexpr(&self) -> Option<&Sexpr> {
+        match self {
+            Self::Sexpr(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_qexpr(&self) -> Option<&Qexpr> {
+        match self {
+            Self::Qexpr(q) => Some(q),
+            _ => None,
+        }
+    }
+
+    pub fn as_map(&self) -> Option<&Map> {
+        match self {
+            Self::Map(m) => Some(m),
+            _ => None,
+        }
+    }
+
+    pub fn as_set(&self) -> Option<&Set> {
+        match self {
+            Self::Set(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_fun(&self) -> Option<&Function> {
+        match self {
+            Self::Fun(fun) => Some(fun),
+            _ => None,
+        }
+    }
+
+    /// An estimate of the memory taken by the value, in bytes.
+    /// Parts shared with other values are counted as if they were not.
+    pub fn size(&self) -> usize {
+        self.size_within(usize::MAX).unwrap_or(usize::MAX)
+    }
+
+    /// Like [`Self::size`], but stops walking the value once it takes more than `limit` bytes,
+    /// returning `None` then. Values sharing their parts may be far larger than their memory.
+    pub(crate) fn size_within(&self, limit: usize) -> Option<usize> {
+        let mut remaining = limit;
+        self.charge(&mut remaining).then(|| limit - remaining)
+    }
+
+    /// Takes the size of the value from `remaining`, failing once that would go below zero.
+    fn charge(&self, remaining: &mut usize) -> bool {
+        let own = std::mem::size_of::<Self>()
+            + match self {
+                Self::Str(s) => s.len(),
+                _ => 0,
+            };
+        let Some(rest) = remaining.checked_sub(own) else {
+            return false;
+        };
+        *remaining = rest;
+        match self {
+            Self::Sexpr(Sexpr(v)) | Self::Qexpr(Qexpr(v)) => v.iter().all(|v| v.charge(remaining)),
+            Self::Map(Map(m)) => m.iter().all(|(k, v)| {
+                let key = std::mem::size_of::<Key>()
+                    + match k {
+                        Key::Str(s) => s.len(),
+                        Key::Num(_) | Key::Sym(_) => 0,
+                    };
+                match remaining.checked_sub(key) {
+                    Some(rest) => {
+                        *remaining = rest;
+                        v.charge(remaining)
+                    }
+                    None => false,
+                }
+            }),
+            Self::Set(Set(s)) => s.iter().all(|v| v.charge(remaining)),
+            Self::Num(_) | Self::Sym(_) | Self::Str(_) | Self::Fun(_) => true,
+        }
+    }
+
     pub fn eval(self, env: &mut Environment) -> Result<Self, anyhow::Error> {
         match self {
             Self::Sym(ref sym) => {
-                let value = env.0.get(sym).context("unbound symbol")?;
+                let value = env.get(*sym).context("unbound symbol")?;
                 Ok(value.clone())
             }
             Self::Sexpr(s) => Sexpr::eval(s, env),
@@ -29,30 +174,48 @@ impl Value {
         }
     }
 
-    pub fn from_pair(pair: Pair<Rule>) -> Result<Option<Self>, anyhow::Error> {
+    /// Evaluates a whole program, on the virtual machine if the environment is configured so.
+    pub fn run(self, env: &mut Environment) -> Result<Self, anyhow::Error> {
+        env.nested(|env| {
+            if env.config.compile {
+                env.compile(self).run(env)
+            } else {
+                self.eval(env)
+            }
+        })
+    }
+
+    pub(crate) fn from_pair(pair: Pair<Rule>) -> Result<Option<Self>, anyhow::Error> {
         let val = match pair.as_rule() {
             Rule::WHITESPACE => return Ok(None),
             Rule::Expr => pair
                 .into_inner()
                 .map(Self::from_pair)
                 .find_map(Result::transpose)
-                .unwrap() // Expression must contain exactly one value as per grammar.
-                .map_err(|_| anyhow::anyhow!("Failed to parse expression"))?,
+                .unwrap()?, // Expression must contain exactly one value as per grammar.
             Rule::Sexpr | Rule::Pils => Self::Sexpr(Sexpr(
                 pair.into_inner()
                     .map(Self::from_pair)
                     .filter_map(Result::transpose)
-                    .collect::<Result<VecDeque<_>, _>>()
-                    .map_err(|_| anyhow::anyhow!("Failed to parse S-Expression"))?,
+                    .collect::<Result<Vector<_>, _>>()?,
             )),
             Rule::Qexpr => Self::Qexpr(Qexpr(
                 pair.into_inner()
                     .map(Self::from_pair)
                     .filter_map(Result::transpose)
-                    .collect::<Result<VecDeque<_>, _>>()
-                    .map_err(|_| anyhow::anyhow!("Failed to parse Q-Expression"))?,
+                    .collect::<Result<Vector<_>, _>>()?,
             )),
-            Rule::Symbol => Self::Sym(pair.as_str().to_string()),
+            Rule::Map => Self::Map(Map::from_pairs(
+                pair.into_inner()
+                    .map(Self::from_pair)
+                    .filter_map(Result::transpose)
+                    .collect::<Result<Vec<_>, _>>()?,
+            )?),
+            Rule::Symbol => Self::Sym(Symbol::new(pair.as_str())),
+            Rule::String => {
+                let quoted = pair.as_str();
+                Self::Str(unescape(&quoted[1..quoted.len() - 1]))
+            }
             Rule::Number => {
                 let x = str::parse::<i64>(pair.as_str())
                     .map_err(|_| anyhow::anyhow!("Failed to parse number"))?;
@@ -63,16 +226,78 @@ impl Value {
     }
 }
 
+/// Resolves the escapes `\n`, `\t`, `\"` and `\\` in the text of a string literal.
+fn unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some(c) => result.push(c),
+            None => {}
+        }
+    }
+    result
+}
+
+/// The elements of an s-expression or q-expression taken by value, as in `for v in qexpr`.
+pub struct Values(pub(crate) im::vector::ConsumingIter<Value>);
+
+impl Iterator for Values {
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Value> {
+        self.0.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for Values {
+    fn next_back(&mut self) -> Option<Value> {
+        self.0.next_back()
+    }
+}
+
+impl ExactSizeIterator for Values {}
+
+/// Parses a single value, like `{1 2}`. Input holding several values parses as an s-expression.
+impl std::str::FromStr for Value {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match crate::parse(s)? {
+            Self::Sexpr(Sexpr(s)) if s.len() == 1 => Ok(s[0].clone()),
+            v => Ok(v),
+        }
+    }
+}
+
 impl std::fmt::Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Num(n) => write!(f, "{n}"),
             Self::Sym(s) => write!(f, "{s}"),
-            // TODO why no '()'?
+            Self::Str(s) => {
+                let escaped = s
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\n")
+                    .replace('\t', "\\t");
+                write!(f, "\"{escaped}\"")
+            }
             Self::Sexpr(s) => write!(f, "{s}"),
-            // TODO why no '{}'?
             Self::Qexpr(q) => write!(f, "{q}"),
-            Self::Fun(_fun) => write!(f, "<function>"),
+            Self::Map(m) => write!(f, "{m}"),
+            Self::Set(s) => write!(f, "{s}"),
+            Self::Fun(fun) => write!(f, "{fun}"),
         }
     }
 }
@@ -84,11 +309,11 @@ mod test {
     #[test]
     fn tail_qexpr() {
         let sexpr = Value::Sexpr(Sexpr::from_iter([
-            Value::Sym("tail".to_string()),
+            Value::Sym(Symbol::new("tail")),
             Value::Qexpr(Qexpr::from_iter([
-                Value::Sym("tail".to_string()),
-                Value::Sym("join".to_string()),
-                Value::Sym("head".to_string()),
+                Value::Sym(Symbol::new("tail")),
+                Value::Sym(Symbol::new("join")),
+                Value::Sym(Symbol::new("head")),
             ])),
         ]));
         let mut env = Environment::default();
@@ -96,16 +321,110 @@ mod test {
         assert_eq!(
             result,
             Value::Qexpr(Qexpr::from_iter([
-                Value::Sym("join".to_string()),
-                Value::Sym("head".to_string()),
+                Value::Sym(Symbol::new("join")),
+                Value::Sym(Symbol::new("head")),
             ]))
         );
     }
 
+    #[test]
+    fn orders_across_kinds() {
+        let mut env = Environment::default();
+        let plus = env.bindings.remove(&Symbol::new("+")).unwrap();
+        let mut values = [
+            plus.clone(),
+            Value::Set(Set::from_iter([Value::Num(1)])),
+            Value::Map(Map::default()),
+            Value::Qexpr(Qexpr::from_iter([])),
+            Value::Sexpr(Sexpr::from_iter([])),
+            Value::Str("a".to_string()),
+            Value::Sym(Symbol::new("a")),
+            Value::Num(100),
+            Value::Num(-1),
+        ];
+        values.sort();
+        assert_eq!(values.first(), Some(&Value::Num(-1)));
+        assert_eq!(values.last(), Some(&plus));
+        assert!(values.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn orders_within_kinds() {
+        assert!(Value::Sym(Symbol::new("a")) < Value::Sym(Symbol::new("b")));
+        assert!(
+            Value::Qexpr(Qexpr::from_iter([Value::Num(1)]))
+                < Value::Qexpr(Qexpr::from_iter([Value::Num(1), Value::Num(0)]))
+        );
+        let env = Environment::default();
+        assert!(env.bindings[&Symbol::new("*")] < env.bindings[&Symbol::new("+")]);
+        assert_eq!(
+            env.bindings[&Symbol::new("+")],
+            env.bindings[&Symbol::new("+")].clone()
+        );
+    }
+
+    #[test]
+    fn usable_as_hash_map_key() {
+        let key = Value::Qexpr(Qexpr::from_iter([Value::Sym(Symbol::new("x"))]));
+        let mut map = std::collections::HashMap::new();
+        map.insert(key.clone(), 1);
+        map.insert(Value::Num(1), 2);
+        assert_eq!(map[&key], 1);
+
+        let mut values = vec![Value::Num(2), Value::Num(1), Value::Num(2)];
+        values.sort();
+        values.dedup();
+        assert_eq!(values, vec![Value::Num(1), Value::Num(2)]);
+    }
+
+    #[test]
+    fn builds_and_inspects_values() {
+        let value = Value::qexpr([
+            Value::num(1),
+            Value::sym("a"),
+            Value::map([(Key::Sym(Symbol::new("k")), Value::set([Value::num(2)]))]),
+        ]);
+        let q = value.as_qexpr().unwrap();
+        assert_eq!(q.len(), 3);
+        assert_eq!(q.get(0).and_then(Value::as_num), Some(1));
+        assert_eq!(
+            q.get(1).and_then(Value::as_sym).map(Symbol::as_str),
+            Some("a")
+        );
+        let map = q.get(2).and_then(Value::as_map).unwrap();
+        let set = map.lookup(&Key::Sym(Symbol::new("k"))).unwrap();
+        assert!(set.as_set().unwrap().contains(&Value::num(2)));
+        assert_eq!(value.as_num(), None);
+        assert_eq!(
+            q.iter().filter_map(Value::as_num).collect::<Vec<_>>(),
+            vec![1]
+        );
+        let values: Values = q.clone().into_iter();
+        assert_eq!(values.len(), 3);
+        assert_eq!(q.clone().into_iter().next_back(), q.get(2).cloned());
+    }
+
+    #[test]
+    fn display_parses_back() {
+        for input in [
+            "-5",
+            "abc",
+            "{ 1 { a } () }",
+            "( + 1 2 )",
+            "#{ a { 1 } b 2 }",
+            "{}",
+            r#"{ "a b" "" "say \"hi\"\\n" }"#,
+        ] {
+            let value = input.parse::<Value>().unwrap();
+            assert_eq!(value.to_string(), input);
+            assert_eq!(value.to_string().parse::<Value>().unwrap(), value);
+        }
+    }
+
     #[test]
     fn list_sexpr_to_qexpr() {
         let value = Value::Sexpr(Sexpr::from_iter([
-            Value::Sym("list".to_string()),
+            Value::Sym(Symbol::new("list")),
             Value::Num(1),
             Value::Num(2),
             Value::Num(3),
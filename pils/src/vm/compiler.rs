@@ -0,0 +1,48 @@
+use super::{Chunk, Op};
+use crate::{sexpr::Sexpr, symbol::Symbol, value::Value};
+use std::collections::HashMap;
+
+/// Lowers a parsed value to bytecode.
+#[derive(Default)]
+pub struct Compiler {
+    chunk: Chunk,
+    slots: HashMap<Symbol, usize>,
+}
+
+impl Compiler {
+    pub fn compile(value: Value) -> Chunk {
+        let mut compiler = Self::default();
+        compiler.value(value);
+        compiler.chunk
+    }
+
+    fn value(&mut self, value: Value) {
+        match value {
+            Value::Sym(sym) => {
+                let slot = self.slot(sym);
+                self.chunk.code.push(Op::Load(slot));
+            }
+            Value::Sexpr(Sexpr(items)) => {
+                let len = items.len();
+                for item in items {
+                    self.value(item);
+                }
+                self.chunk.code.push(Op::Apply(len));
+            }
+            v => {
+                self.chunk.constants.push(v);
+                self.chunk
+                    .code
+                    .push(Op::Const(self.chunk.constants.len() - 1));
+            }
+        }
+    }
+
+    /// Each distinct symbol gets one slot, so that repeated lookups can share a resolution.
+    fn slot(&mut self, sym: Symbol) -> usize {
+        *self.slots.entry(sym).or_insert_with(|| {
+            self.chunk.names.push(sym);
+            self.chunk.names.len() - 1
+        })
+    }
+}
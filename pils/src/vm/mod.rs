@@ -0,0 +1,248 @@
+use crate::{
+    environment::Environment,
+    sexpr::{Arithmetic, Sexpr},
+    symbol::Symbol,
+    value::Value,
+};
+use anyhow::Context;
+use compiler::Compiler;
+
+mod compiler;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    /// Push a constant.
+    Const(usize),
+    /// Push the value bound to the symbol in a slot.
+    Load(usize),
+    /// Pop that many values and apply them like an evaluated s-expression.
+    Apply(usize),
+}
+
+/// Compiled bytecode along with the constants and symbols it refers to.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+    pub constants: Vec<Value>,
+    pub names: Vec<Symbol>,
+}
+
+impl Chunk {
+    pub fn compile(value: Value) -> Self {
+        Compiler::compile(value)
+    }
+
+    pub fn run(&self, env: &mut Environment) -> Result<Value, anyhow::Error> {
+        Vm::new(self, env).run()
+    }
+}
+
+/// A value resolved for a slot, along with the arithmetic to compute calls to it directly
+/// if it is bound to one of the numeric builtins.
+#[derive(Clone)]
+struct Resolved {
+    value: Value,
+    arithmetic: Option<Arithmetic>,
+}
+
+/// A stack machine executing a chunk.
+/// Loaded values keep their arithmetic, so that applying them needs no lookup by name.
+struct Vm<'a> {
+    chunk: &'a Chunk,
+    env: &'a mut Environment,
+    stack: Vec<(Value, Option<Arithmetic>)>,
+    /// Values resolved for each slot, valid while the environment is unchanged.
+    resolved: Vec<Option<Resolved>>,
+    generation: u64,
+}
+
+impl<'a> Vm<'a> {
+    fn new(chunk: &'a Chunk, env: &'a mut Environment) -> Self {
+        let generation = env.generation;
+        Self {
+            chunk,
+            env,
+            stack: Vec::new(),
+            resolved: vec![None; chunk.names.len()],
+            generation,
+        }
+    }
+
+    fn run(mut self) -> Result<Value, anyhow::Error> {
+        for op in &self.chunk.code {
+            match *op {
+                Op::Const(index) => self.stack.push((self.chunk.constants[index].clone(), None)),
+                Op::Load(slot) => {
+                    let Resolved { value, arithmetic } = self.load(slot)?;
+                    self.stack.push((value, arithmetic));
+                }
+                Op::Apply(len) => {
+                    self.env.step()?;
+                    let args = self.stack.split_off(self.stack.len() - len);
+                    let value = match Self::arithmetic(&args) {
+                        Some(result) => Value::Num(result?),
+                        None => {
+                            Sexpr::from_iter(args.into_iter().map(|(v, _)| v)).apply(self.env)?
+                        }
+                    };
+                    self.stack.push((value, None));
+                }
+            }
+        }
+        self.stack.pop().map(|(v, _)| v).context("empty chunk")
+    }
+
+    /// Calls to the arithmetic builtins with only numbers as arguments are computed directly.
+    fn arithmetic(args: &[(Value, Option<Arithmetic>)]) -> Option<Result<i64, anyhow::Error>> {
+        let [(_, Some(arithmetic)), operands @ ..] = args else {
+            return None;
+        };
+        let numbers = operands
+            .iter()
+            .map(|(v, _)| match v {
+                Value::Num(n) => Some(*n),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+        if numbers.is_empty() {
+            return None;
+        }
+        Some(arithmetic.apply(&numbers))
+    }
+
+    fn load(&mut self, slot: usize) -> Result<Resolved, anyhow::Error> {
+        if self.generation != self.env.generation {
+            self.resolved.fill(None);
+            self.generation = self.env.generation;
+        }
+        if let Some(resolved) = &self.resolved[slot] {
+            return Ok(resolved.clone());
+        }
+        let sym = self.chunk.names[slot];
+        let value = self.env.get(sym).context("unbound symbol")?.clone();
+        let arithmetic = match &value {
+            Value::Fun(fun) if self.env.is_builtin(sym, &value) => Arithmetic::of(fun.name),
+            _ => None,
+        };
+        let resolved = Resolved { value, arithmetic };
+        self.resolved[slot] = Some(resolved.clone());
+        Ok(resolved)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{environment::CHUNK_CACHE_SIZE, parse};
+    use std::sync::Arc;
+
+    /// Runs the programs in order on both the tree walker and the virtual machine,
+    /// each with its own environment, and checks that every result agrees.
+    fn agree(programs: &[&str]) {
+        let mut walked = Environment::default();
+        let mut compiled = Environment::default();
+        for program in programs {
+            let value = parse(program).unwrap();
+            let expected = value.clone().eval(&mut walked).map(|v| v.to_string());
+            let actual = Chunk::compile(value)
+                .run(&mut compiled)
+                .map(|v| v.to_string());
+            match (expected, actual) {
+                (Ok(expected), Ok(actual)) => assert_eq!(expected, actual, "{program}"),
+                (Err(expected), Err(actual)) => {
+                    assert_eq!(expected.to_string(), actual.to_string(), "{program}");
+                }
+                (expected, actual) => panic!("{program}: {expected:?} vs {actual:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn compiles_to_slots() {
+        let chunk = Chunk::compile(parse("+ x (* x 2)").unwrap());
+        assert_eq!(
+            chunk.code,
+            [
+                Op::Load(0),
+                Op::Load(1),
+                Op::Load(2),
+                Op::Load(1),
+                Op::Const(0),
+                Op::Apply(3),
+                Op::Apply(3),
+            ]
+        );
+        assert_eq!(
+            chunk.names,
+            [Symbol::new("+"), Symbol::new("x"), Symbol::new("*")]
+        );
+    }
+
+    #[test]
+    fn agrees_with_tree_walker() {
+        agree(&[
+            "* 2 (+ 4 5) (/ 10 2) (-2) (- 1 2 3)",
+            "{ * 1 2 3 }",
+            "join { { 1 2 3 } { 4 ( 5 6 ) } }",
+            "+",
+            "eval (head {5 10 11 15})",
+            "(eval (head {+ - + - * /})) 10 20",
+            "hello",
+            "eval { tail ( list 1 2 3 4 ) }",
+            "/ 1 0",
+            "1 2 3",
+            "()",
+            "",
+        ]);
+    }
+
+    #[test]
+    fn agrees_on_definitions() {
+        agree(&[
+            "def {x} 100",
+            "def {y} 200",
+            "+ x y",
+            "def {a b} 5 6",
+            "+ a b",
+            "def {arglist} {a b x y}",
+            "def arglist 1 2 3 4",
+            "list a b x y",
+            "list x (def {x} 7) x",
+        ]);
+    }
+
+    #[test]
+    fn agrees_on_collections() {
+        agree(&[
+            "def {m} #{ x 1 y 2 }",
+            "assoc m {z x} 3 4",
+            "get m {y}",
+            "get m {q}",
+            "union (set 1 2) (set 2 3)",
+            "member? (set 1 2 3) 2",
+        ]);
+    }
+
+    #[test]
+    fn evicts_least_recently_used_chunks() {
+        let mut env = Environment::default();
+        let program = parse("+ 1 2").unwrap();
+        let hot = env.compile(program.clone());
+        let cold = env.compile(Value::Num(-1));
+        for n in (0..).take(CHUNK_CACHE_SIZE) {
+            env.compile(Value::Num(n));
+            assert!(Arc::ptr_eq(&hot, &env.compile(program.clone())));
+        }
+        assert!(!Arc::ptr_eq(&cold, &env.compile(Value::Num(-1))));
+    }
+
+    #[test]
+    fn agrees_on_match() {
+        agree(&[
+            "def {x} 1",
+            "+ x (match {2 3} {{x & _} {* x 10}}) x",
+            "match 4 {1 1 _ {+ x 1}}",
+            "match 4 {1 1}",
+        ]);
+    }
+}
@@ -1,8 +1,36 @@
 use anyhow::Context;
-use pils::{help::HELP_TEXT, process};
+use pils::{config::Config, help::HELP_TEXT, Interpreter};
 use rustyline::{error::ReadlineError, DefaultEditor};
+use std::path::Path;
 
 fn main() -> anyhow::Result<()> {
+    let args = std::env::args().collect::<Vec<_>>();
+    let mut pils = Interpreter::new(Config {
+        compile: args.iter().any(|arg| arg == "--compile"),
+        fold_constants: !args.iter().any(|arg| arg == "--no-fold"),
+        check_types: args.iter().any(|arg| arg == "--check"),
+        fuel: args
+            .iter()
+            .find_map(|arg| arg.strip_prefix("--fuel="))
+            .map(str::parse)
+            .transpose()
+            .context("--fuel takes a number of steps")?,
+        max_depth: args
+            .iter()
+            .find_map(|arg| arg.strip_prefix("--depth="))
+            .map(str::parse)
+            .transpose()
+            .context("--depth takes a number of levels")?,
+        memory_limit: args
+            .iter()
+            .find_map(|arg| arg.strip_prefix("--memory="))
+            .map(str::parse)
+            .transpose()
+            .context("--memory takes a number of bytes")?,
+    });
+    for file in args.iter().skip(1).filter(|arg| !arg.starts_with("--")) {
+        println!("{}", pils.process_file(Path::new(file))?);
+    }
     let mut prompt = DefaultEditor::new().context("Failed to create prompt")?;
     loop {
         match prompt.readline("pils >> ") {
@@ -16,9 +44,34 @@ fn main() -> anyhow::Result<()> {
                     continue;
                 }
 
+                if line == "memory" {
+                    match pils.config().memory_limit {
+                        Some(limit) => println!("{} of {limit} bytes", pils.memory_usage()),
+                        None => println!("Memory is only tracked with --memory"),
+                    }
+                    continue;
+                }
+
+                if let Some(path) = line.strip_prefix("save ") {
+                    if let Err(e) = pils.save_image(Path::new(path.trim())) {
+                        println!("Error: {e:#}");
+                    }
+                    continue;
+                }
+
+                if let Some(path) = line.strip_prefix("load ") {
+                    if let Err(e) = pils.load_image(Path::new(path.trim())) {
+                        println!("Error: {e:#}");
+                    }
+                    continue;
+                }
+
                 prompt.add_history_entry(&line)?;
 
-                println!("{}", process(line.as_str())?);
+                match pils.process(line.as_str()) {
+                    Ok(value) => println!("{value}"),
+                    Err(e) => println!("Error: {e:#}"),
+                }
             }
             Err(ReadlineError::Interrupted) => {
                 println!("CTRL-C");